@@ -60,11 +60,11 @@ impl BeetleRank {
         Ok(&self.cups)
     }
 
-    pub fn post_log(&self, user: String, guildhall: String, file: String, ) -> Result<Vec<String>> {
+    pub fn post_log(&self, user: String, guildhall: String, file: String, file_name: String) -> Result<Vec<String>> {
         let client = reqwest::Client::builder().use_rustls_tls().build()?;
         let url = "https://www.beetlerank.com/upload-log";
-        let filepart = reqwest::multipart::Part::bytes(fs::read(file.clone()).unwrap())
-            .file_name(file);
+        let filepart = reqwest::multipart::Part::bytes(fs::read(file).unwrap())
+            .file_name(file_name);
         let form = reqwest::multipart::Form::new()
             .text("user", user)
             .text("guildhall", guildhall)
@@ -98,6 +98,16 @@ impl BeetleRank {
         Ok(data)
     }
 
+    /// Downloads the racelog that was uploaded along with a ranked time
+    pub fn get_log(rank: &Rank) -> Result<String> {
+        let client = reqwest::Client::builder().use_rustls_tls().build()?;
+        let url = format!("https://www.beetlerank.com/uploads/logs/{}", rank.file);
+        let res = block_on(client.get(url).send())?.error_for_status()?;
+        let data = block_on(res.text())?;
+
+        Ok(data)
+    }
+
     pub fn get_tracks(&mut self, cup: &String) -> Result<Vec<String>> {
         let tracks = self.tracks.entry(cup.clone()).or_insert_with(||{
             let client = reqwest::Client::builder().use_rustls_tls().build().expect("Failed to build client");
@@ -122,7 +132,7 @@ pub struct Rank {
     pub laptime: f64,
     date: String,
     map: String,
-    file: String,
+    pub file: String,
 }
 
 impl Default for Rank {
@@ -0,0 +1,75 @@
+/// Bits of each dot in a braille character, indexed by [row][column] within the cell
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// A drawing surface made of braille characters, each holding 2x4 dots
+///
+/// Coordinates are in dots, starting at the top left. Anything drawn outside the canvas is
+/// dropped, so callers don't have to clip. Glyphs replace the dots of the cell they land in.
+pub struct BrailleCanvas {
+    width: usize,
+    height: usize,
+    dots: Vec<u8>,
+    glyphs: Vec<Option<char>>,
+}
+
+impl BrailleCanvas {
+    /// Canvas `width` characters wide and `height` characters tall
+    pub fn new(width: usize, height: usize) -> BrailleCanvas {
+        BrailleCanvas {
+            width,
+            height,
+            dots: vec![0u8; width * height],
+            glyphs: vec![None; width * height],
+        }
+    }
+
+    pub fn dot_width(&self) -> usize {
+        self.width * 2
+    }
+
+    pub fn dot_height(&self) -> usize {
+        self.height * 4
+    }
+
+    pub fn set(&mut self, x: i64, y: i64) {
+        if let Some(cell) = self.cell(x, y) {
+            self.dots[cell] |= BRAILLE_DOTS[(y % 4) as usize][(x % 2) as usize];
+        }
+    }
+
+    pub fn line(&mut self, from: (i64, i64), to: (i64, i64)) {
+        let steps = i64::max((to.0 - from.0).abs(), (to.1 - from.1).abs()).max(1);
+        for step in 0..=steps {
+            let x = from.0 + (to.0 - from.0) * step / steps;
+            let y = from.1 + (to.1 - from.1) * step / steps;
+            self.set(x, y);
+        }
+    }
+
+    /// Draws a character over the cell containing the dot at `x`, `y`
+    pub fn glyph(&mut self, x: i64, y: i64, glyph: char) {
+        if let Some(cell) = self.cell(x, y) {
+            self.glyphs[cell] = Some(glyph);
+        }
+    }
+
+    pub fn rows(&self) -> Vec<String> {
+        (0..self.height).map(|row| {
+            (0..self.width).map(|col| {
+                let cell = row * self.width + col;
+                match (self.glyphs[cell], self.dots[cell]) {
+                    (Some(glyph), _) => glyph,
+                    (None, 0) => ' ',
+                    (None, dots) => char::from_u32(0x2800 + dots as u32).unwrap_or(' '),
+                }
+            }).collect()
+        }).collect()
+    }
+
+    fn cell(&self, x: i64, y: i64) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.dot_width() || y as usize >= self.dot_height() {
+            return None
+        }
+        Some((y as usize / 4) * self.width + x as usize / 2)
+    }
+}
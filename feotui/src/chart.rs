@@ -0,0 +1,73 @@
+/// Width of the value labels drawn left of a chart
+const LABEL_WIDTH: usize = 9;
+
+/// Bars of a sparkline, from lowest to highest
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Draws values as a line chart `width` columns wide and `height` rows tall, with the y-axis
+/// labelled at the top and bottom
+///
+/// When there are more values than columns, each column shows the average of the values that
+/// fall into it. A flat line is drawn across the middle. The zero line is drawn whenever it lies inside the chart.
+pub fn line_chart(values: &[f32], width: usize, height: usize) -> Vec<String> {
+    if values.is_empty() || width == 0 || height == 0 {
+        return Vec::new()
+    }
+    let columns = columns(values, width);
+
+    let max = columns.iter().copied().fold(f32::MIN, f32::max);
+    let min = columns.iter().copied().fold(f32::MAX, f32::min);
+    // rounding noise would otherwise spread a flat line over the whole chart
+    let flat = max - min <= 1e-4 * max.abs().max(1f32);
+    let row_of = |value: f32| match flat {
+        true => (height - 1) / 2,
+        false => (((max - value) / (max - min)) * (height - 1) as f32).round() as usize,
+    };
+    let zero_row = (min <= 0f32 && max >= 0f32).then(|| row_of(0f32));
+
+    (0..height).map(|row| {
+        let label = match row {
+            0 => format!("{:>w$.2}", max, w = LABEL_WIDTH - 1),
+            r if r == height - 1 => format!("{:>w$.2}", min, w = LABEL_WIDTH - 1),
+            _ => " ".repeat(LABEL_WIDTH - 1),
+        };
+        let line: String = columns.iter().map(|value| {
+            if row_of(*value) == row {
+                '•'
+            } else if zero_row == Some(row) {
+                '─'
+            } else {
+                ' '
+            }
+        }).collect();
+        format!("{}┤{}", label, line)
+    }).collect()
+}
+
+/// Draws values as a single row of bars at most `width` columns wide, scaled from zero (or the
+/// lowest value, if it's negative) to the highest value
+///
+/// When there are more values than columns, each column shows the average of the values that
+/// fall into it.
+pub fn sparkline(values: &[f32], width: usize) -> String {
+    if values.is_empty() || width == 0 {
+        return String::new()
+    }
+    let columns = columns(values, width);
+    let max = columns.iter().copied().fold(f32::MIN, f32::max);
+    let min = columns.iter().copied().fold(0f32, f32::min);
+    columns.iter().map(|value| {
+        let level = if max > min { (value - min) / (max - min) } else { 0f32 };
+        BARS[((level * (BARS.len() - 1) as f32).round() as usize).min(BARS.len() - 1)]
+    }).collect()
+}
+
+/// Splits values into at most `width` columns and averages each
+fn columns(values: &[f32], width: usize) -> Vec<f32> {
+    let count = width.min(values.len());
+    (0..count).map(|col| {
+        let start = col * values.len() / count;
+        let end = ((col + 1) * values.len() / count).max(start + 1);
+        values[start..end].iter().sum::<f32>() / (end - start) as f32
+    }).collect()
+}
@@ -1,8 +1,12 @@
 mod window;
 mod stateful_list;
 mod util;
+mod chart;
+mod canvas;
 
 
 pub use window::*;
 pub use stateful_list::*;
-pub use util::*;
\ No newline at end of file
+pub use util::*;
+pub use chart::*;
+pub use canvas::*;
@@ -7,6 +7,31 @@ pub enum BorderStyle {
     Ascii,
 }
 
+/// Removes the ANSI escape sequences that colour text, leaving what is shown on screen
+pub fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            // a control sequence ends with its first character from @ to ~, after the [
+            chars.next();
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Number of columns a line takes up on screen, not counting colour codes
+pub fn visible_width(line: &str) -> usize {
+    strip_ansi(line).graphemes(true).count()
+}
+
 pub trait Border {
     fn border(&self, style: BorderStyle) -> Self;
 }
@@ -20,7 +45,7 @@ impl Border for Vec<String> {
             BorderStyle::None => "",
         }.graphemes(true).collect();
         let mut out: Vec<String> = Vec::new();
-        let width = self.iter().map(|s| s.graphemes(true).count()).max().unwrap_or(0);
+        let width = self.iter().map(|s| visible_width(s)).max().unwrap_or(0);
 
         out.push(format!(" {}{}{} ", border[0], border[2].repeat(width), border[1]));
         for line in self {
@@ -37,10 +62,10 @@ pub trait Padding {
 
 impl Padding for Vec<String> {
     fn pad(&self, padding: usize) -> Self {
-        let width = self.iter().map(|s| s.graphemes(true).count()).max().unwrap_or(0);
+        let width = self.iter().map(|s| visible_width(s)).max().unwrap_or(0);
         let mut out: Vec<String> = Vec::new();
         for line in self {
-            out.push(format!("{}{: <width$}{}", " ".repeat(padding), &line, " ".repeat(padding), width=width));
+            out.push(format!("{}{}{}{}", " ".repeat(padding), &line, " ".repeat(width - visible_width(line)), " ".repeat(padding)));
         }
         out
     }
@@ -63,16 +88,18 @@ impl Popup for Vec<String> {
     fn popup(&self, lines: &Vec<String>, x: usize, y: usize) -> Self {
         // start with the unaffected lines before it
         let mut out: Vec<String> = self[0..y].to_vec();
-        let width = self.iter().map(|s| s.graphemes(true).count()).max().unwrap_or(0);
+        let width = self.iter().map(|s| visible_width(s)).max().unwrap_or(0);
         for idx in y..y+lines.len() {
             let line = &lines[idx-y];
-            let length = line.graphemes(true).count();
-            // draw inside the original content
+            let length = visible_width(line);
+            // draw inside the original content, which loses its colours since they could spill into the popup
             if idx < self.len() {
-                if x+length < self[idx].graphemes(true).count() {
-                    out.push(format!("{}{}{}", &self[idx].graphemes(true).collect::<Vec<_>>()[..x].join(""), &line, &self[idx].graphemes(true).collect::<Vec<_>>()[x+length..].join("")));
+                let base = strip_ansi(&self[idx]);
+                let base: Vec<&str> = base.graphemes(true).collect();
+                if x+length < base.len() {
+                    out.push(format!("{}{}{}", &base[..x].join(""), &line, &base[x+length..].join("")));
                 } else {
-                    out.push(format!("{}{}", &self[idx].graphemes(true).collect::<Vec<_>>()[..x].join(""), &line));
+                    out.push(format!("{}{}", &base[..x].join(""), &line));
                 }
                 
             }
@@ -97,4 +124,42 @@ impl Render for Vec<String> {
     fn render(&self) -> String {
         self.join("\n")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREEN: &str = "\x1b[38;5;10m";
+    const RESET: &str = "\x1b[39m";
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi(&format!("Save: {}00:01:250{}", GREEN, RESET)), "Save: 00:01:250");
+        assert_eq!(strip_ansi("\x1b[1;30;41mVOID\x1b[0m"), "VOID");
+        assert_eq!(strip_ansi("plain ↗ text"), "plain ↗ text");
+        // an escape that doesn't start a control sequence is left alone
+        assert_eq!(strip_ansi("a\x1bb"), "a\x1bb");
+    }
+
+    #[test]
+    fn test_visible_width() {
+        assert_eq!(visible_width(&format!("{}-00:01:250{}", GREEN, RESET)), 10);
+        assert_eq!(visible_width("↗ 35°"), 5);
+        assert_eq!(visible_width(""), 0);
+    }
+
+    #[test]
+    fn test_coloured_layout() {
+        let lines = vec![format!("{}ab{}", GREEN, RESET), String::from("abcd")];
+        let padded = lines.pad(1);
+        assert_eq!(padded.iter().map(|line| visible_width(line)).collect::<Vec<usize>>(), vec![6, 6]);
+        let bordered = lines.border(BorderStyle::Ascii);
+        assert_eq!(bordered[0], " +----+ ");
+
+        let popup = padded.popup(&vec![String::from("XY")], 2, 0);
+        // the colours of the line underneath are dropped, so they can't spill into the popup
+        assert_eq!(popup[0], " aXY  ");
+        assert_eq!(popup[1], padded[1]);
+    }
+}
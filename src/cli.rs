@@ -0,0 +1,492 @@
+use anyhow::{bail, Context, Result};
+
+use chrono::{DateTime, Utc};
+use std::{path::Path, time::Duration};
+
+use crate::track_creator::{custom_course_path, save_custom_course, COURSE_DIR};
+use crate::speedometer::{autoplace::{course_from_log, AutoPlaceSettings}, checkpoint::DEFAULT_RADIUS, course::Course, coursesettings::CourseSettings, geoexport::{self, MapTransforms}, geometry::{CourseStats, DEFAULT_AVERAGE_SPEED}, history::{RunFilter, RunHistory}, livesplit::{LiveSplitSettings, SETTINGS_PATH}, logcompare::{align_logs, to_csv}, logstore::{LogStore, RetentionPolicy}, lss::LiveSplitRun, markerpack::MarkerPack, racelog::RaceLogEntry, retime::Retime, splits::{update_track_data, RaceLap}, trail::{CheckpointPlacement, Trail}, util::{Exportable, Importable, Timestamp}};
+
+/// Spacing between checkpoints generated from a trail when none is given
+const DEFAULT_TRAIL_SPACING: f32 = 150f32;
+
+/// Distance along the course between compared samples of two race logs
+const COMPARE_STEP: f32 = 10f32;
+/// Distance along the course between rows of the log comparison table
+const COMPARE_TABLE_STEP: f32 = 250f32;
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 8;
+
+const USAGE: &str = "Usage: speedylemon [COMMAND]
+
+Runs the speedometer when no command is given.
+
+Commands:
+  markers <course.csv> <map id> <output.xml> [racelog.csv]
+      Export a course as a TacO/Blish HUD marker pack, with an optional racing line trail
+  trail-course <trail.trl> <name> [spacing <units> | curvature <degrees> [min spacing]]
+      Generate a custom course from a TacO trail, keeping the trail as its reference line
+  reference-line <trail.trl> <track>
+      Use a TacO trail as the reference line shown while racing a track
+  log-course <racelog.csv> <name> [spacing <units>] [heading <degrees>] [elevation <units>]
+      Generate a custom course from a past run, placing checkpoints like recording in the Track Creator.
+      Rules that aren't given use their defaults, and 'off' turns a rule off
+  course-report <course.csv> [speed]
+      Show the length, climb and turns of a course, and how long it takes at an average speed in units per second
+  reverse <track> <name>
+      Save a custom course that drives a track from its end back to its start
+  trim <track> <from> <to> <name>
+      Save a custom course made of the checkpoints from one index to another of a track
+  merge <track> <track> <name>
+      Save a custom course that drives one track and then another
+  history <track> [status <finished|reset|invalid>] [character <name>] [limit <count>]
+      List past attempts at a track, oldest first
+  lss export <track> <output.lss>
+      Save a track's PB, best segments and run history as LiveSplit splits
+  lss import <input.lss> <track> [character]
+      Load LiveSplit splits into a track's PB, best segments and run history, keeping local times that are faster.
+      Attempts are credited to the given character, or \"LiveSplit\" if none is given
+  retime <racelog.csv> <track>
+      Replay a race log through a track's checkpoints and show the times it scores
+  compare-logs <racelog.csv> <racelog.csv> <track> [output.csv]
+      Compare two race logs at the same points along a track: how far behind the second run is, how much faster
+      it goes and how far off the first run's line it drives. Charts are shown unless a CSV file is given to save to
+  rebuild-splits <track>
+      Rebuild a track's PB and best segments from the race logs saved for it
+  geo <geojson|gpx|csv> <track> <map id> <output> [racelog.csv | log id]
+      Export a track, and a run on it, in continent coordinates for GIS and plotting tools.
+      GPX and CSV need a run. The map has to have been visited with the speedometer running
+  logs list [track]
+      List saved race logs with their IDs, oldest first
+  logs prune [keep <count>] [compress <days>]
+      Delete all but the PBs and the latest logs of each track, and gzip logs older than some days.
+      Rules that aren't given are taken from data/logstore.toml, which is also applied after every run
+  logs export <id> <output.csv>
+      Save a copy of a race log, decompressed
+  missed-checkpoint <track> <recover|void>
+      Choose whether reaching a later checkpoint of a track before the next one lets the racer go back for it,
+      or voids the run. Runs can be recovered unless set otherwise
+  livesplit <address | off>
+      Drive a LiveSplit timer through its LiveSplit Server component while racing, e.g. localhost:16834
+
+Tracks are named the way the track selector lists them, e.g. \"TYRIA INF.LEAP\" or \"custom_courses/My Course\"";
+
+/// Runs a single command-line command instead of the speedometer
+pub fn run(args: &[String]) -> Result<()> {
+    match args[0].as_str() {
+        "markers" => markers(&args[1..]),
+        "trail-course" => trail_course(&args[1..]),
+        "reference-line" => reference_line(&args[1..]),
+        "log-course" => log_course(&args[1..]),
+        "course-report" => course_report(&args[1..]),
+        "reverse" => reverse(&args[1..]),
+        "trim" => trim(&args[1..]),
+        "merge" => merge(&args[1..]),
+        "history" => history(&args[1..]),
+        "lss" => lss(&args[1..]),
+        "missed-checkpoint" => missed_checkpoint(&args[1..]),
+        "livesplit" => livesplit(&args[1..]),
+        "retime" => retime(&args[1..]),
+        "compare-logs" => compare_logs(&args[1..]),
+        "rebuild-splits" => rebuild_splits(&args[1..]),
+        "logs" => logs(&args[1..]),
+        "geo" => geo(&args[1..]),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        command => bail!("Unknown command: {}\n\n{}", command, USAGE),
+    }
+}
+
+fn markers(args: &[String]) -> Result<()> {
+    if args.len() < 3 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let course = Course::from_path(&args[0]).context(format!("Failed to load course from {}", args[0]))?;
+    let map_id: u32 = args[1].parse().context(format!("Invalid map id: {}", args[1]))?;
+    let mut pack = MarkerPack::new(&course, map_id);
+    if let Some(logpath) = args.get(3) {
+        let log = Vec::<RaceLogEntry>::import(logpath)?.context(format!("No race log at {}", logpath))?;
+        pack = pack.with_trail(Trail::from_log(map_id, &log));
+    }
+    pack.export(args[2].clone())
+}
+
+fn trail_course(args: &[String]) -> Result<()> {
+    if args.len() < 2 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let trail = Trail::import(&args[0])?.context(format!("Trail file {} does not exist", args[0]))?;
+    let placement = match args.get(2).map(|s| s.as_str()) {
+        None => CheckpointPlacement::Spacing(DEFAULT_TRAIL_SPACING),
+        Some("spacing") => CheckpointPlacement::Spacing(parse_arg(args.get(3), DEFAULT_TRAIL_SPACING)?),
+        Some("curvature") => CheckpointPlacement::Curvature {
+            degrees: parse_arg(args.get(3), 45f32)?,
+            min_spacing: parse_arg(args.get(4), DEFAULT_TRAIL_SPACING / 2f32)?,
+        },
+        Some(placement) => bail!("Unknown checkpoint placement: {}", placement),
+    };
+    let mut course = trail.to_course(&args[1], placement, DEFAULT_RADIUS);
+    save_new_course(&mut course, &args[1])?;
+    trail.export(format!("data/trails/{}.trl", course.name))
+}
+
+fn reference_line(args: &[String]) -> Result<()> {
+    if args.len() < 2 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let trail = Trail::import(&args[0])?.context(format!("Trail file {} does not exist", args[0]))?;
+    trail.export(format!("data/trails/{}.trl", args[1]))
+}
+
+fn log_course(args: &[String]) -> Result<()> {
+    if args.len() < 2 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let log = Vec::<RaceLogEntry>::import(&args[0])?.context(format!("No race log at {}", args[0]))?;
+    let mut settings = AutoPlaceSettings::default();
+    for option in args[2..].chunks(2) {
+        let value: Option<f32> = match option.get(1) {
+            Some(value) if value == "off" => None,
+            Some(value) => Some(value.parse().ok().context(format!("Invalid value: {}", value))?),
+            None => bail!("Missing value for {}", option[0]),
+        };
+        match option[0].as_str() {
+            "spacing" => settings.spacing = value,
+            "heading" => settings.heading = value,
+            "elevation" => settings.elevation = value,
+            rule => bail!("Unknown placement rule: {}", rule),
+        }
+    }
+    let mut course = course_from_log(&args[1], &log, settings, DEFAULT_RADIUS);
+    save_new_course(&mut course, &args[1])
+}
+
+fn course_report(args: &[String]) -> Result<()> {
+    let path = args.first().context(format!("Missing arguments\n\n{}", USAGE))?;
+    let speed = parse_arg(args.get(1), DEFAULT_AVERAGE_SPEED)?;
+    let course = Course::from_path(path).context(format!("Failed to load course from {}", path))?;
+    let stats = CourseStats::new(&course);
+
+    println!("Course: {}", course.name);
+    println!("Checkpoints: {}", course.checkpoints.len());
+    println!("Straight-line length: {:.1}", stats.straight_length);
+    println!("Estimated path length: {:.1}", stats.path_length);
+    println!("Elevation gain: {:.1}", stats.elevation_gain);
+    println!("Elevation loss: {:.1}", stats.elevation_loss);
+    if let Some((idx, angle)) = stats.tightest_turn {
+        println!("Tightest turn: {:.1}° at checkpoint {}", angle, idx);
+    }
+    println!("Expected time at {} units/s: {}", speed, stats.expected_time(speed).timestamp());
+    println!("----- Segments -----");
+    for (idx, distance) in stats.segments.iter().enumerate() {
+        println!("Checkpoint {: >2} -> {: >2}: {:.1}", idx, idx + 1, distance);
+    }
+    Ok(())
+}
+
+fn reverse(args: &[String]) -> Result<()> {
+    if args.len() < 2 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let mut course = load_track(&args[0])?.reversed(&args[1]);
+    save_new_course(&mut course, &args[1])
+}
+
+fn trim(args: &[String]) -> Result<()> {
+    if args.len() < 4 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let from: usize = args[1].parse().context(format!("Invalid checkpoint index: {}", args[1]))?;
+    let to: usize = args[2].parse().context(format!("Invalid checkpoint index: {}", args[2]))?;
+    let mut course = load_track(&args[0])?.trimmed(&args[3], from, to)?;
+    save_new_course(&mut course, &args[3])
+}
+
+fn merge(args: &[String]) -> Result<()> {
+    if args.len() < 3 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let mut course = load_track(&args[0])?.merged(&load_track(&args[1])?, &args[2]);
+    save_new_course(&mut course, &args[2])
+}
+
+fn history(args: &[String]) -> Result<()> {
+    let track = args.first().context(format!("Missing arguments\n\n{}", USAGE))?;
+    let mut filter = RunFilter::default();
+    for option in args[1..].chunks(2) {
+        let value = option.get(1).context(format!("Missing value for {}", option[0]))?;
+        match option[0].as_str() {
+            "status" => filter.status = Some(value.parse()?),
+            "character" => filter.character = Some(value.clone()),
+            "limit" => filter.limit = Some(value.parse().context(format!("Invalid limit: {}", value))?),
+            option => bail!("Unknown history filter: {}", option),
+        }
+    }
+    let history = RunHistory::load(track)?;
+    for run in history.query(&filter) {
+        let splits = run.splits().iter().map(|t| Duration::from_millis(*t).timestamp()).collect::<Vec<String>>().join(" ");
+        println!("{} {: <8} {} [{}] {} {}", run.date.format("%Y-%m-%d %H:%M:%S"), run.status, run.character, run.fingerprint, splits, run.log_path.as_deref().unwrap_or(""));
+        if let Some(techniques) = &run.techniques {
+            println!("    Lap: {}", techniques);
+            for (idx, segment) in run.segment_techniques.iter().enumerate() {
+                println!("    CP {: >2}: {}", idx + 1, segment);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lss(args: &[String]) -> Result<()> {
+    if args.len() < 3 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    match args[0].as_str() {
+        "export" => {
+            let course = load_track(&args[1])?;
+            let lap = RaceLap::import(&format!("data/splits/{}.toml", args[1]))?;
+            let history = RunHistory::load(&args[1])?;
+            LiveSplitRun::new(&course, lap.as_ref(), &history).export(args[2].clone())
+        },
+        "import" => {
+            let run = LiveSplitRun::import(&args[1])?.context(format!("Splits file {} does not exist", args[1]))?;
+            let course = load_track(&args[2])?;
+            if run.segments.len() + 1 != course.checkpoints.len() {
+                bail!("{} has {} segments but {} has {} checkpoints", args[1], run.segments.len(), args[2], course.checkpoints.len());
+            }
+            if let Some(lap) = run.race_lap() {
+                // the local PB and best segments stay whenever they're faster than the imported ones
+                let path = format!("data/splits/{}.toml", args[2]);
+                let lap = match RaceLap::import(&path)? {
+                    Some(local) if local.splits.best.len() != lap.splits.best.len() => bail!("{} has splits for {} segments, but {} has {}", args[2], local.splits.best.len(), args[1], lap.splits.best.len()),
+                    Some(local) => local.merged(&lap),
+                    None => lap,
+                };
+                lap.export(path)?;
+            }
+            let mut history = RunHistory::load(&args[2])?;
+            history.merge(run.run_history(&course, args.get(3).map_or("LiveSplit", |s| s.as_str())));
+            history.export(RunHistory::path(&args[2]))?;
+            println!("Imported {} attempts into {}", run.attempts.len(), args[2]);
+            Ok(())
+        },
+        action => bail!("Unknown lss action: {}", action),
+    }
+}
+
+fn missed_checkpoint(args: &[String]) -> Result<()> {
+    if args.len() < 2 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let mut settings = CourseSettings::load(&args[0])?;
+    settings.missed_checkpoint = args[1].parse()?;
+    settings.export(CourseSettings::path(&args[0]))
+}
+
+fn livesplit(args: &[String]) -> Result<()> {
+    let address = args.first().context(format!("Missing arguments\n\n{}", USAGE))?;
+    let mut settings = LiveSplitSettings::load()?;
+    settings.enabled = address != "off";
+    if settings.enabled {
+        settings.address = address.clone();
+    }
+    settings.export(SETTINGS_PATH.to_string())
+}
+
+fn retime(args: &[String]) -> Result<()> {
+    if args.len() < 2 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let log = Vec::<RaceLogEntry>::import(&args[0])?.context(format!("No race log at {}", args[0]))?;
+    let course = load_track(&args[1])?;
+    let retime = Retime::new(&course, CourseSettings::load(&args[1])?.missed_checkpoint, &log);
+    for (idx, time) in retime.checkpoint_times.iter().enumerate() {
+        println!("Checkpoint {: >2}: {}", idx, time.timestamp());
+    }
+    println!("Resets: {}", retime.resets);
+    match retime.laptime() {
+        Some(laptime) => println!("Valid run, lap time: {}", laptime.timestamp()),
+        None => println!("Invalid run, reached checkpoint {} of {}", retime.checkpoint_times.len(), course.checkpoints.len()),
+    }
+    Ok(())
+}
+
+fn compare_logs(args: &[String]) -> Result<()> {
+    if args.len() < 3 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let first = Vec::<RaceLogEntry>::import(&args[0])?.context(format!("No race log at {}", args[0]))?;
+    let second = Vec::<RaceLogEntry>::import(&args[1])?.context(format!("No race log at {}", args[1]))?;
+    let course = load_track(&args[2])?;
+    let samples = align_logs(&course, &first, &second, COMPARE_STEP);
+    if samples.is_empty() {
+        bail!("The race logs don't follow {}", args[2]);
+    }
+    if let Some(output) = args.get(3) {
+        std::fs::write(output, to_csv(&samples)).context(format!("Failed to write {}", output))?;
+        println!("Saved {} samples to {}", samples.len(), output);
+        return Ok(())
+    }
+
+    println!("{: >8}  {: >10}  {: >11}  {: >11}", "PROGRESS", "TIME DELTA", "SPEED DELTA", "LINE OFFSET");
+    let every = (COMPARE_TABLE_STEP / COMPARE_STEP) as usize;
+    for sample in samples.iter().step_by(every) {
+        println!("{: >8.0}  {: >+10.3}  {: >+11.1}  {: >+11.1}", sample.progress, sample.time_delta, sample.speed_delta, sample.line_offset);
+    }
+    let charts: [(&str, Vec<f32>); 3] = [
+        ("Time delta (s)", samples.iter().map(|s| s.time_delta as f32).collect()),
+        ("Speed delta", samples.iter().map(|s| s.speed_delta).collect()),
+        ("Line offset", samples.iter().map(|s| s.line_offset).collect()),
+    ];
+    for (title, values) in charts {
+        println!("\n{}", title);
+        for line in feotui::line_chart(&values, CHART_WIDTH, CHART_HEIGHT) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+fn rebuild_splits(args: &[String]) -> Result<()> {
+    let track = args.first().context(format!("Missing arguments\n\n{}", USAGE))?;
+    let course = load_track(track)?;
+    let policy = CourseSettings::load(track)?.missed_checkpoint;
+    let logs = Path::new("data/logs").join(track);
+    let (Some(dir), Some(stem)) = (logs.parent(), logs.file_name()) else {
+        bail!("Invalid track name: {}", track);
+    };
+    let prefix = format!("{}_", stem.to_string_lossy());
+    let mut rebuilt = 0;
+    // logs saved before the log store existed, which a new install or custom course has none of
+    let legacy = match dir.exists() {
+        true => std::fs::read_dir(dir).context(format!("Failed to read logs in {}", dir.display()))?.collect::<std::io::Result<Vec<_>>>()?,
+        false => Vec::new(),
+    };
+    for entry in legacy {
+        let path = entry.path();
+        let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        // logs are named after the track and their lap time, so other tracks sharing the prefix are skipped
+        let is_track_log = name.strip_prefix(&prefix).is_some_and(|laptime| laptime.chars().all(|c| c.is_ascii_digit()));
+        if !is_track_log || path.extension().is_none_or(|ext| ext != "csv") {
+            continue
+        }
+        let log = Vec::<RaceLogEntry>::import(&path.to_string_lossy().to_string())?.unwrap_or_default();
+        match Retime::new(&course, policy, &log).finished_times() {
+            Some(times) => {
+                update_track_data(times, format!("data/splits/{}.toml", track))?;
+                rebuilt += 1;
+            },
+            None => println!("Skipping {}, it doesn't finish the course", path.display()),
+        }
+    }
+    let store = LogStore::load()?;
+    for stored in store.query(Some(track)).into_iter().filter(|stored| stored.valid) {
+        match Retime::new(&course, policy, &store.read(stored)?).finished_times() {
+            Some(times) => {
+                update_track_data(times, format!("data/splits/{}.toml", track))?;
+                rebuilt += 1;
+            },
+            None => println!("Skipping log {}, it doesn't finish the course", stored.id),
+        }
+    }
+    println!("Rebuilt splits for {} from {} logs", track, rebuilt);
+    Ok(())
+}
+
+fn logs(args: &[String]) -> Result<()> {
+    let action = args.first().context(format!("Missing arguments\n\n{}", USAGE))?;
+    let mut store = LogStore::load()?;
+    match action.as_str() {
+        "list" => {
+            for stored in store.query(args.get(1).map(|s| s.as_str())) {
+                let validity = if stored.valid { "" } else { " (invalid)" };
+                println!("{} {} {} {} {}{}", stored.id, stored.date.format("%Y-%m-%d %H:%M:%S"), stored.course, stored.character, Duration::from_millis(stored.laptime).timestamp(), validity);
+            }
+            Ok(())
+        },
+        "prune" => {
+            let mut policy = RetentionPolicy::load()?;
+            for option in args[1..].chunks(2) {
+                let value = option.get(1).context(format!("Missing value for {}", option[0]))?;
+                match option[0].as_str() {
+                    "keep" => policy.keep_last = Some(value.parse().context(format!("Invalid count: {}", value))?),
+                    "compress" => policy.compress_after_days = Some(value.parse().context(format!("Invalid number of days: {}", value))?),
+                    option => bail!("Unknown prune rule: {}", option),
+                }
+            }
+            let (removed, missing, compressed) = store.apply(&policy)?;
+            RunHistory::forget_deleted_logs(&store, &[removed.as_slice(), missing.as_slice()].concat())?;
+            println!("Deleted {} logs and compressed {}", removed.len(), compressed);
+            if !missing.is_empty() {
+                println!("Forgot {} logs whose files were already gone", missing.len());
+            }
+            Ok(())
+        },
+        "export" => {
+            if args.len() < 3 {
+                bail!("Missing arguments\n\n{}", USAGE);
+            }
+            let stored = store.get(&args[1]).context(format!("No race log with ID {}", args[1]))?;
+            store.read(stored)?.export(args[2].clone())
+        },
+        action => bail!("Unknown logs action: {}", action),
+    }
+}
+
+fn geo(args: &[String]) -> Result<()> {
+    if args.len() < 4 {
+        bail!("Missing arguments\n\n{}", USAGE);
+    }
+    let course = load_track(&args[1])?;
+    let map_id: u32 = args[2].parse().context(format!("Invalid map id: {}", args[2]))?;
+    let transform = MapTransforms::load()?.get(map_id).context(format!("No continent coordinates known for map {}, race on it once first", map_id))?;
+    let run = args.get(4).map(|log| load_log(log)).transpose()?;
+    let output = match (args[0].as_str(), &run) {
+        ("geojson", _) => geoexport::to_geojson(&course, run.as_ref().map(|(log, _)| log.as_slice()), &transform),
+        ("gpx", Some((log, saved))) => {
+            let start = *saved - chrono::Duration::milliseconds((log.last().map_or(0f64, |entry| entry.timestamp) * 1000f64) as i64);
+            geoexport::to_gpx(&course.name, log, &transform, start)
+        },
+        ("csv", Some((log, _))) => geoexport::to_csv(&course, log, &transform),
+        ("gpx" | "csv", None) => bail!("Exporting as {} needs a race log", args[0]),
+        (format, _) => bail!("Unknown format: {}", format),
+    };
+    std::fs::write(&args[3], output).context(format!("Failed to write {}", args[3]))?;
+    Ok(())
+}
+
+/// Reads a race log given by its ID in the log store or its path, along with when it was saved
+fn load_log(log: &str) -> Result<(Vec<RaceLogEntry>, DateTime<Utc>)> {
+    let store = LogStore::load()?;
+    if let Some(stored) = store.get(log) {
+        return Ok((store.read(stored)?, stored.date.with_timezone(&Utc)))
+    }
+    let entries = Vec::<RaceLogEntry>::import(&log.to_string())?.context(format!("No race log at {}", log))?;
+    let saved = std::fs::metadata(log)?.modified()?;
+    Ok((entries, saved.into()))
+}
+
+fn load_track(track: &str) -> Result<Course> {
+    let path = format!("{}/{}.csv", COURSE_DIR, track);
+    let mut course = Course::from_path(&path).context(format!("Failed to load course from {}", path))?;
+    course.name = track.to_string();
+    Ok(course)
+}
+
+/// Saves a generated course to the custom library, refusing to replace an existing one
+fn save_new_course(course: &mut Course, name: &str) -> Result<()> {
+    if Path::new(&custom_course_path(name)).exists() {
+        bail!("A custom course called {} already exists", name);
+    }
+    save_custom_course(course, name)?;
+    println!("Created {} with {} checkpoints", course.name, course.checkpoints.len());
+    Ok(())
+}
+
+/// Parses an optional argument, falling back to `default` when it is missing
+fn parse_arg<T: std::str::FromStr>(arg: Option<&String>, default: T) -> Result<T> {
+    match arg {
+        Some(value) => value.parse().ok().context(format!("Invalid value: {}", value)),
+        None => Ok(default),
+    }
+}
@@ -1,3 +1,7 @@
+mod cli;
+mod minimap;
+mod practice;
+mod track_creator;
 mod track_selector;
 mod speedylemon;
 mod speedometer;
@@ -12,6 +16,16 @@ thread_local!(static DEBUG: Cell<bool> = Cell::new(false));
 async fn main() {
     pretty_env_logger::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        // log output is disabled in release builds, so command errors go straight to stderr
+        if let Err(err) = cli::run(&args) {
+            eprintln!("Error: {:?}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if let Err(err) = speedylemon::run() {
         log::error!("Error: {:?}", err);
         std::process::exit(1);
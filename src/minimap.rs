@@ -0,0 +1,137 @@
+use feotui::BrailleCanvas;
+
+use crate::speedometer::{course::Course, guild_wars_handler::Position, polyline::Polyline};
+
+/// Size of the minimap in characters
+const WIDTH: usize = 40;
+const HEIGHT: usize = 12;
+
+/// Units shown to either side of the racer when following them
+const FOLLOW_RADIUS: f32 = 400f32;
+
+/// Arrows for the racer's heading, going clockwise from north
+const HEADINGS: [char; 8] = ['↑', '↗', '→', '↘', '↓', '↙', '←', '↖'];
+
+/// Minimap draws a top-down view of the course around the racer
+///
+/// By default the whole course is in view, widened to keep the racer on the map when they leave
+/// it. Following zooms in on a fixed area centered on the racer instead.
+pub struct Minimap {
+    pub follow: bool,
+}
+
+/// How the minimap turns map positions into canvas dots, with north at the top
+struct Viewport {
+    center: [f32; 2],
+    scale: f32,
+    size: [f32; 2],
+}
+
+impl Viewport {
+    /// Viewport fitting every point given, keeping the same scale on both axes
+    fn fit(points: &[[f32; 2]], size: [f32; 2]) -> Viewport {
+        let min = points.iter().fold([f32::MAX; 2], |min, p| [min[0].min(p[0]), min[1].min(p[1])]);
+        let max = points.iter().fold([f32::MIN; 2], |max, p| [max[0].max(p[0]), max[1].max(p[1])]);
+        let span = [(max[0] - min[0]).max(1f32), (max[1] - min[1]).max(1f32)];
+        Viewport {
+            center: [(min[0] + max[0]) / 2f32, (min[1] + max[1]) / 2f32],
+            // one dot of margin on each side, so points on the edge stay visible
+            scale: f32::min((size[0] - 2f32) / span[0], (size[1] - 2f32) / span[1]),
+            size,
+        }
+    }
+
+    fn dot(&self, position: &Position) -> (i64, i64) {
+        let x = (position[0] - self.center[0]) * self.scale + self.size[0] / 2f32;
+        let y = self.size[1] / 2f32 - (position[2] - self.center[1]) * self.scale;
+        (x.floor() as i64, y.floor() as i64)
+    }
+}
+
+impl Minimap {
+    pub fn new() -> Minimap {
+        Minimap { follow: false }
+    }
+
+    pub fn toggle_follow(&mut self) {
+        self.follow = !self.follow;
+    }
+
+    /// Draws the course, the line to follow if there is one, and the racer facing `heading`
+    ///
+    /// `heading` is in radians clockwise from north. The next checkpoint to collect is drawn
+    /// bigger than the others.
+    pub fn view(&self, course: &Course, next_checkpoint: usize, racer: &Position, heading: Option<f32>, line: Option<&Polyline>) -> Vec<String> {
+        let mut canvas = BrailleCanvas::new(WIDTH, HEIGHT);
+        let size = [canvas.dot_width() as f32, canvas.dot_height() as f32];
+        let viewport = match self.follow {
+            true => Viewport::fit(&[[racer[0] - FOLLOW_RADIUS, racer[2] - FOLLOW_RADIUS], [racer[0] + FOLLOW_RADIUS, racer[2] + FOLLOW_RADIUS]], size),
+            false => {
+                let mut points: Vec<[f32; 2]> = course.checkpoints.iter().chain(course.reset.iter()).map(|cp| [cp.x, cp.z]).collect();
+                points.push([racer[0], racer[2]]);
+                Viewport::fit(&points, size)
+            },
+        };
+
+        if let Some(line) = line {
+            for segment in line.points.windows(2) {
+                canvas.line(viewport.dot(&segment[0]), viewport.dot(&segment[1]));
+            }
+        }
+        for (idx, checkpoint) in course.checkpoints.iter().enumerate() {
+            let glyph = match idx {
+                0 => 'S',
+                idx if idx + 1 == course.checkpoints.len() => 'F',
+                idx if idx == next_checkpoint => 'O',
+                _ => 'o',
+            };
+            let (x, y) = viewport.dot(&checkpoint.point());
+            canvas.glyph(x, y, glyph);
+        }
+        if let Some(reset) = &course.reset {
+            let (x, y) = viewport.dot(&reset.point());
+            canvas.glyph(x, y, 'R');
+        }
+        let (x, y) = viewport.dot(racer);
+        canvas.glyph(x, y, heading.map_or('●', heading_arrow));
+
+        let mut lines = vec![format!("Minimap ({}, z to change)", if self.follow { "following" } else { "whole course" })];
+        lines.append(&mut canvas.rows());
+        lines
+    }
+}
+
+/// Arrow closest to a heading in radians clockwise from north
+fn heading_arrow(heading: f32) -> char {
+    let sector = (heading.rem_euclid(std::f32::consts::TAU) / (std::f32::consts::TAU / 8f32)).round() as usize;
+    HEADINGS[sector % 8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heading_arrow() {
+        use std::f32::consts::PI;
+        assert_eq!(heading_arrow(0.0), '↑');
+        assert_eq!(heading_arrow(PI / 2.0), '→');
+        assert_eq!(heading_arrow(-PI / 2.0), '←');
+        assert_eq!(heading_arrow(2.0 * PI - 0.1), '↑');
+    }
+
+    #[test]
+    fn test_view() {
+        let mut course = Course::from_points("test-course", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [100.0, 0.0, 100.0]], 15);
+        course.add_reset(0.0, 0.0, 100.0, 15);
+        let lines = Minimap::new().view(&course, 1, &[50.0, 0.0, 0.0], Some(std::f32::consts::PI / 2.0), None);
+        assert_eq!(lines.len(), HEIGHT + 1);
+        let map = lines[1..].join("\n");
+        for glyph in ['S', 'O', 'F', 'R', '→'] {
+            assert_eq!(map.matches(glyph).count(), 1, "{}", map);
+        }
+        // north is up, so the finish and reset are on the top row and the start at the bottom
+        assert!(lines[1].contains('F') && lines[1].contains('R'));
+        assert!(lines[HEIGHT].contains('S'));
+    }
+}
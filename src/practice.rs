@@ -0,0 +1,83 @@
+/// PracticeSelector picks the checkpoints a practice run starts and ends at
+///
+/// The start always stays before the end, so every range it gives has at least one segment.
+pub struct PracticeSelector {
+    pub from: usize,
+    pub to: usize,
+    /// Whether the arrow keys move the end instead of the start
+    pub editing_end: bool,
+    last: usize,
+}
+
+impl PracticeSelector {
+    /// Selector covering a whole course with `checkpoints` checkpoints
+    pub fn new(checkpoints: usize) -> PracticeSelector {
+        let last = checkpoints.saturating_sub(1);
+        PracticeSelector {
+            from: 0,
+            to: last,
+            editing_end: false,
+            last,
+        }
+    }
+
+    pub fn toggle_field(&mut self) {
+        self.editing_end = !self.editing_end;
+    }
+
+    pub fn increase(&mut self) {
+        if self.editing_end {
+            self.to = usize::min(self.to + 1, self.last);
+        } else if self.from + 1 < self.to {
+            self.from += 1;
+        }
+    }
+
+    pub fn decrease(&mut self) {
+        if self.editing_end {
+            if self.to > self.from + 1 {
+                self.to -= 1;
+            }
+        } else {
+            self.from = self.from.saturating_sub(1);
+        }
+    }
+
+    /// The chosen range, if the course is long enough to practice
+    pub fn range(&self) -> Option<(usize, usize)> {
+        (self.from < self.to).then_some((self.from, self.to))
+    }
+
+    pub fn view(&self) -> Vec<String> {
+        let marker = |editing: bool| if editing { ">" } else { " " };
+        vec![
+            "Practice Mode".to_string(),
+            "-------------".to_string(),
+            format!("{} Start at checkpoint: {: >2}", marker(!self.editing_end), self.from),
+            format!("{} End at checkpoint:   {: >2}", marker(self.editing_end), self.to),
+            "-------------".to_string(),
+            "Up/Down: change  Left/Right: switch  Enter: practice  Esc: cancel".to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_stays_ordered() {
+        let mut selector = PracticeSelector::new(4);
+        assert_eq!(selector.range(), Some((0, 3)));
+        for _ in 0..5 {
+            selector.increase();
+        }
+        assert_eq!(selector.range(), Some((2, 3)));
+        selector.toggle_field();
+        selector.decrease();
+        assert_eq!(selector.range(), Some((2, 3)));
+        selector.increase();
+        assert_eq!(selector.range(), Some((2, 3)));
+        assert_eq!(PracticeSelector::new(1).range(), None);
+    }
+}
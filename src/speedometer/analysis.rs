@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use super::racelog::RaceLogEntry;
+use super::splits::RaceLap;
+use super::technique::{self, TechniqueSummary};
+
+/// How a single segment of a finished run went
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentAnalysis {
+    /// Checkpoint the segment ends at
+    pub checkpoint: usize,
+    /// Milliseconds taken on the segment
+    pub split: u64,
+    /// Milliseconds slower than the PB split, negative when faster
+    pub pb_delta: Option<i64>,
+    /// Milliseconds slower than the best split ever driven, negative when faster
+    pub gold_delta: Option<i64>,
+    /// Speed when crossing the checkpoint at the end of the segment
+    pub entry_speed: Option<f32>,
+    pub min_speed: Option<f32>,
+    pub avg_speed: Option<f32>,
+    pub max_speed: Option<f32>,
+    pub techniques: TechniqueSummary,
+}
+
+/// Breakdown of a finished run, checkpoint by checkpoint
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaceAnalysis {
+    pub laptime: u64,
+    pub segments: Vec<SegmentAnalysis>,
+    /// Techniques used over the whole lap
+    pub techniques: TechniqueSummary,
+}
+
+impl RaceAnalysis {
+    /// Analyses a run from its checkpoint times and race log
+    ///
+    /// `previous` has to be the PB as it stood before this run, since a new PB would otherwise
+    /// be compared against itself.
+    pub fn new(checkpoint_times: &[Duration], log: &[RaceLogEntry], previous: Option<&RaceLap>) -> RaceAnalysis {
+        let phases = technique::phases(log);
+        let segments = checkpoint_times.windows(2).enumerate().map(|(idx, w)| {
+            let split = w[1].saturating_sub(w[0]).as_millis() as u64;
+            let (start, end) = (w[0].as_secs_f64(), w[1].as_secs_f64());
+            let speeds: Vec<f32> = log.iter()
+                .filter(|entry| entry.timestamp >= start && entry.timestamp <= end)
+                .map(|entry| entry.speed)
+                .collect();
+            let delta = |times: &Vec<u64>| times.get(idx).map(|time| split as i64 - *time as i64);
+            SegmentAnalysis {
+                checkpoint: idx + 1,
+                split,
+                pb_delta: previous.and_then(|lap| delta(&lap.splits.pb)),
+                gold_delta: previous.and_then(|lap| delta(&lap.splits.best)),
+                entry_speed: log.iter()
+                    .min_by(|a, b| (a.timestamp - end).abs().partial_cmp(&(b.timestamp - end).abs()).unwrap())
+                    .map(|entry| entry.speed),
+                min_speed: speeds.iter().copied().reduce(f32::min),
+                avg_speed: (!speeds.is_empty()).then(|| speeds.iter().sum::<f32>() / speeds.len() as f32),
+                max_speed: speeds.iter().copied().reduce(f32::max),
+                techniques: TechniqueSummary::new(&phases, start, end),
+            }
+        }).collect();
+
+        RaceAnalysis {
+            laptime: checkpoint_times.last().map_or(0, |time| time.as_millis() as u64),
+            segments,
+            techniques: TechniqueSummary::new(&phases, 0f64, checkpoint_times.last().map_or(0f64, |time| time.as_secs_f64())),
+        }
+    }
+
+    /// Up to `count` segments where the most time was lost against the PB, worst first
+    pub fn biggest_losses(&self, count: usize) -> Vec<&SegmentAnalysis> {
+        let mut losses: Vec<&SegmentAnalysis> = self.segments.iter()
+            .filter(|segment| segment.pb_delta.is_some_and(|delta| delta > 0))
+            .collect();
+        losses.sort_by_key(|segment| std::cmp::Reverse(segment.pb_delta));
+        losses.truncate(count);
+        losses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speedometer::splits::Splits;
+
+    #[test]
+    fn test_analysis() {
+        let times: Vec<Duration> = [0, 1000, 2500, 3000].iter().map(|t| Duration::from_millis(*t)).collect();
+        let log: Vec<RaceLogEntry> = (0..=30).map(|step| RaceLogEntry::sample([0.0; 3], step as f32, step as f64 / 10.0)).collect();
+        let previous = RaceLap {
+            pb_laptime: 2700,
+            splits: Splits {
+                pb: vec![900, 1300, 500],
+                best: vec![800, 1200, 400],
+            },
+        };
+        let analysis = RaceAnalysis::new(&times, &log, Some(&previous));
+        assert_eq!(analysis.laptime, 3000);
+        assert_eq!(analysis.segments.len(), 3);
+
+        let first = &analysis.segments[0];
+        assert_eq!((first.split, first.pb_delta, first.gold_delta), (1000, Some(100), Some(200)));
+        assert_eq!(first.entry_speed, Some(10.0));
+        assert_eq!((first.min_speed, first.max_speed), (Some(0.0), Some(10.0)));
+        assert_eq!(first.avg_speed, Some(5.0));
+        // speeds climb by 1 every 100ms, so the whole lap is spent coasting
+        assert_eq!(first.techniques.coasting.duration, 1000);
+        assert_eq!(analysis.techniques.coasting.duration, 3000);
+
+        let losses: Vec<usize> = analysis.biggest_losses(3).iter().map(|segment| segment.checkpoint).collect();
+        assert_eq!(losses, vec![2, 1]);
+        assert!(RaceAnalysis::new(&times, &log, None).biggest_losses(3).is_empty());
+    }
+}
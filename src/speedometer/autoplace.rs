@@ -0,0 +1,168 @@
+use super::course::Course;
+use super::guild_wars_handler::Position;
+use super::racelog::RaceLogEntry;
+use super::util::{euclidian_distance_2d, euclidian_distance_3d};
+
+/// Distance the racer has to move before their heading is measured again, so that standing
+/// still or jittering in place doesn't register as turning
+const HEADING_SAMPLE_DISTANCE: f32 = 10f32;
+
+/// Closest that a turn or climb will place a checkpoint to the previous one
+const MIN_GAP: f32 = 30f32;
+
+/// Rules for when the AutoPlacer drops a checkpoint. Any rule set to None is ignored
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoPlaceSettings {
+    /// Units travelled since the last checkpoint
+    pub spacing: Option<f32>,
+    /// Degrees turned since the last checkpoint
+    pub heading: Option<f32>,
+    /// Units climbed or dropped since the last checkpoint
+    pub elevation: Option<f32>,
+}
+
+impl Default for AutoPlaceSettings {
+    fn default() -> Self {
+        AutoPlaceSettings {
+            spacing: Some(150f32),
+            heading: Some(45f32),
+            elevation: Some(20f32),
+        }
+    }
+}
+
+/// AutoPlacer follows the racer's path and decides where checkpoints should go
+pub struct AutoPlacer {
+    pub settings: AutoPlaceSettings,
+    last_placed: Option<Position>,
+    last_sample: Option<Position>,
+    travelled: f32,
+    heading_anchor: Option<Position>,
+    placed_heading: Option<f32>,
+}
+
+impl AutoPlacer {
+    pub fn new(settings: AutoPlaceSettings) -> AutoPlacer {
+        AutoPlacer {
+            settings,
+            last_placed: None,
+            last_sample: None,
+            travelled: 0f32,
+            heading_anchor: None,
+            placed_heading: None,
+        }
+    }
+
+    /// Feeds the racer's latest position, returning it if a checkpoint belongs there
+    ///
+    /// The very first position is always placed so the course has a start.
+    pub fn sample(&mut self, position: Position) -> Option<Position> {
+        let Some(last_placed) = self.last_placed else {
+            return self.place(position, None)
+        };
+        if let Some(last) = self.last_sample {
+            self.travelled += euclidian_distance_3d(&last, &position);
+        }
+        self.last_sample = Some(position);
+
+        let heading = self.update_heading(position);
+        let gap = euclidian_distance_3d(&last_placed, &position);
+
+        if self.settings.spacing.is_some_and(|spacing| self.travelled >= spacing) {
+            return self.place(position, heading)
+        }
+        if gap < MIN_GAP {
+            return None
+        }
+        if self.settings.elevation.is_some_and(|elevation| (position[1] - last_placed[1]).abs() >= elevation) {
+            return self.place(position, heading)
+        }
+        if let (Some(threshold), Some(placed), Some(current)) = (self.settings.heading, self.placed_heading, heading) {
+            if heading_difference(placed, current) >= threshold {
+                return self.place(position, heading)
+            }
+        }
+        if self.placed_heading.is_none() {
+            self.placed_heading = heading;
+        }
+        None
+    }
+
+    fn place(&mut self, position: Position, heading: Option<f32>) -> Option<Position> {
+        self.last_placed = Some(position);
+        self.last_sample = Some(position);
+        self.travelled = 0f32;
+        self.placed_heading = heading;
+        Some(position)
+    }
+
+    /// Heading in degrees the racer is moving in, measured over the last few units travelled
+    fn update_heading(&mut self, position: Position) -> Option<f32> {
+        let anchor = *self.heading_anchor.get_or_insert(position);
+        if euclidian_distance_2d(&anchor, &position) < HEADING_SAMPLE_DISTANCE {
+            return None
+        }
+        self.heading_anchor = Some(position);
+        Some((position[2] - anchor[2]).atan2(position[0] - anchor[0]).to_degrees())
+    }
+}
+
+/// Smallest angle in degrees between two headings
+fn heading_difference(a: f32, b: f32) -> f32 {
+    let difference = (a - b).abs() % 360f32;
+    if difference > 180f32 { 360f32 - difference } else { difference }
+}
+
+/// Generates a course from a past run, placing checkpoints the same way as recording live
+pub fn course_from_log(name: &str, log: &[RaceLogEntry], settings: AutoPlaceSettings, radius: i32) -> Course {
+    let mut placer = AutoPlacer::new(settings);
+    let mut points: Vec<Position> = log.iter()
+        .filter_map(|entry| placer.sample([entry.x, entry.y, entry.z]))
+        .collect();
+    if let Some(last) = log.last() {
+        let end = [last.x, last.y, last.z];
+        if points.last() != Some(&end) {
+            points.push(end);
+        }
+    }
+    Course::from_points(name, &points, radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(x: f32, y: f32, z: f32) -> RaceLogEntry {
+        RaceLogEntry::sample([x, y, z], 0.0, 0.0)
+    }
+
+    #[test]
+    fn test_spacing() {
+        let settings = AutoPlaceSettings { spacing: Some(100.0), heading: None, elevation: None };
+        let log: Vec<RaceLogEntry> = (0..=250).step_by(5).map(|x| entry(x as f32, 0.0, 0.0)).collect();
+        let course = course_from_log("spaced", &log, settings, 15);
+        let xs: Vec<f32> = course.checkpoints.iter().map(|cp| cp.x).collect();
+        assert_eq!(xs, vec![0.0, 100.0, 200.0, 250.0]);
+    }
+
+    #[test]
+    fn test_heading() {
+        let settings = AutoPlaceSettings { spacing: None, heading: Some(45.0), elevation: None };
+        let log: Vec<RaceLogEntry> = (0..=100).step_by(5).map(|x| entry(x as f32, 0.0, 0.0))
+            .chain((5..=100).step_by(5).map(|z| entry(100.0, 0.0, z as f32)))
+            .collect();
+        let course = course_from_log("corner", &log, settings, 15);
+        assert_eq!(course.checkpoints.len(), 3);
+        assert_eq!(course.checkpoints[1].point(), [100.0, 0.0, 10.0]);
+        assert_eq!(course.checkpoints[2].point(), [100.0, 0.0, 100.0]);
+    }
+
+    #[test]
+    fn test_elevation() {
+        let settings = AutoPlaceSettings { spacing: None, heading: None, elevation: Some(20.0) };
+        let log: Vec<RaceLogEntry> = (0..=100).step_by(5).map(|x| entry(x as f32, x as f32 / 2.0, 0.0)).collect();
+        let course = course_from_log("hill", &log, settings, 15);
+        let ys: Vec<f32> = course.checkpoints.iter().map(|cp| cp.y).collect();
+        assert_eq!(ys, vec![0.0, 20.0, 40.0, 50.0]);
+    }
+}
@@ -2,12 +2,15 @@
 use super::guild_wars_handler::Position;
 pub struct Camera {
     pub position: Position,
+    /// Direction the camera is looking in
+    pub front: Position,
 }
 
 impl Camera {
     pub fn new() -> Camera {
         Camera {
             position: [0f32; 3],
+            front: [0f32; 3],
         }
     }
 }
\ No newline at end of file
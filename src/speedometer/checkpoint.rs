@@ -1,5 +1,10 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
+/// Radius used for checkpoints when none is given
+pub const DEFAULT_RADIUS: i32 = 15;
+
 #[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
 #[serde(default)]
 pub struct Checkpoint {
@@ -25,12 +30,22 @@ impl Checkpoint {
             x: 0f32,
             y: 0f32,
             z: 0f32,
-            radius: 15i32,
+            radius: DEFAULT_RADIUS,
         }
     }
     pub fn point(&self) -> [f32; 3] {
         [self.x, self.y, self.z]
     }
+
+    /// Name shown for the checkpoint in marker packs and splits
+    pub fn label(&self) -> String {
+        match self.stepname {
+            Stepname::Start => "Start".to_string(),
+            Stepname::End => "End".to_string(),
+            Stepname::Reset => "Reset".to_string(),
+            Stepname::Checkpoint => format!("Checkpoint {}", self.step),
+        }
+    }
 }
 
 impl Default for Checkpoint {
@@ -41,7 +56,7 @@ impl Default for Checkpoint {
             x: 0f32,
             y: 0f32,
             z: 0f32,
-            radius: 15i32,
+            radius: DEFAULT_RADIUS,
         }
     }
 }
@@ -56,4 +71,15 @@ pub enum Stepname {
     End,
     #[serde(rename = "*")]
     Checkpoint,
+}
+
+impl Display for Stepname {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match *self {
+            Self::Start => "Start",
+            Self::Reset => "Reset",
+            Self::End => "End",
+            Self::Checkpoint => "CP",
+        })
+    }
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+use std::{fmt::Display, time::Duration};
+
+use super::history::{RunFilter, RunHistory, RunStatus};
+use super::splits::RaceLap;
+
+/// Which earlier run the current one is measured against
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Comparison {
+    PersonalBest,
+    /// The fastest time ever driven on each segment
+    BestSegments,
+    Average,
+    Median,
+    LatestRun,
+    /// The #1 ranked run on Beetlerank
+    BeetlerankTop,
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match *self {
+            Self::PersonalBest => "Personal Best",
+            Self::BestSegments => "Best Segments",
+            Self::Average => "Average",
+            Self::Median => "Median",
+            Self::LatestRun => "Latest Run",
+            Self::BeetlerankTop => "Beetlerank #1",
+        })
+    }
+}
+
+impl Comparison {
+    /// The comparison after this one, wrapping back around to the Personal Best
+    pub fn next(self) -> Comparison {
+        match self {
+            Self::PersonalBest => Self::BestSegments,
+            Self::BestSegments => Self::Average,
+            Self::Average => Self::Median,
+            Self::Median => Self::LatestRun,
+            Self::LatestRun => Self::BeetlerankTop,
+            Self::BeetlerankTop => Self::PersonalBest,
+        }
+    }
+
+    /// Time in milliseconds the comparison took on each segment, if there is a run to compare against
+    ///
+    /// Average, Median and Latest Run only use finished runs driven on the same layout of the course.
+    pub fn splits(self, lap: Option<&RaceLap>, history: &RunHistory, fingerprint: &str, top: Option<&[u64]>) -> Option<Vec<u64>> {
+        let finished = || history.query(&RunFilter {
+            status: Some(RunStatus::Finished),
+            fingerprint: Some(fingerprint.to_string()),
+            ..Default::default()
+        }).iter().map(|run| run.splits()).collect::<Vec<Vec<u64>>>();
+        let splits = match self {
+            Self::PersonalBest => lap.map(|lap| lap.splits.pb.clone()),
+            Self::BestSegments => lap.map(|lap| lap.splits.best.clone()),
+            Self::Average => per_segment(&finished(), |times| times.iter().sum::<u64>() / times.len() as u64),
+            Self::Median => per_segment(&finished(), |times| {
+                times.sort();
+                let mid = times.len() / 2;
+                if times.len() % 2 == 0 { (times[mid - 1] + times[mid]) / 2 } else { times[mid] }
+            }),
+            Self::LatestRun => finished().pop(),
+            Self::BeetlerankTop => top.map(|top| top.to_vec()),
+        };
+        splits.filter(|splits| !splits.is_empty())
+    }
+}
+
+/// Combines the time every run took on each segment into a single time per segment
+fn per_segment(runs: &[Vec<u64>], combine: impl Fn(&mut Vec<u64>) -> u64) -> Option<Vec<u64>> {
+    let segments = runs.iter().map(|run| run.len()).min()?;
+    Some((0..segments).map(|idx| {
+        let mut times: Vec<u64> = runs.iter().map(|run| run[idx]).collect();
+        combine(&mut times)
+    }).collect())
+}
+
+/// Time gained or lost on the segment ending at checkpoint `idx`, negative when ahead
+pub fn segment_delta(splits: &[u64], checkpoint_times: &[Duration], idx: usize) -> Option<i64> {
+    let (Some(end), Some(start)) = (checkpoint_times.get(idx), checkpoint_times.get(idx.checked_sub(1)?)) else {
+        return None
+    };
+    let split = *splits.get(idx - 1)?;
+    Some(end.saturating_sub(*start).as_millis() as i64 - split as i64)
+}
+
+/// Final time the run is heading for if it drives the rest of the course like the comparison
+pub fn projected_final(splits: &[u64], checkpoint_times: &[Duration]) -> Option<Duration> {
+    let last = checkpoint_times.len().checked_sub(1)?;
+    let remaining: u64 = splits.get(last..)?.iter().sum();
+    Some(checkpoint_times[last] + Duration::from_millis(remaining))
+}
+
+/// Final time the run is heading for `elapsed` after the start, if it drives the rest of the
+/// course like the comparison
+///
+/// Unlike projected_final this keeps moving between checkpoints: once the current segment takes
+/// longer than the comparison, the extra time is added on.
+pub fn live_projection(splits: &[u64], checkpoint_times: &[Duration], elapsed: Duration) -> Option<Duration> {
+    let last = checkpoint_times.len().checked_sub(1)?;
+    let Some(current) = splits.get(last) else {
+        return projected_final(splits, checkpoint_times)
+    };
+    let remaining: u64 = splits[last + 1..].iter().sum();
+    let segment_end = Duration::max(checkpoint_times[last] + Duration::from_millis(*current), elapsed);
+    Some(segment_end + Duration::from_millis(remaining))
+}
+
+/// Milliseconds that could be saved on each segment by matching the best time ever driven on it
+pub fn possible_time_save(lap: &RaceLap) -> Vec<i64> {
+    lap.splits.pb.iter().zip(lap.splits.best.iter()).map(|(pb, best)| *pb as i64 - *best as i64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speedometer::course::Course;
+    use crate::speedometer::history::RunRecord;
+    use crate::speedometer::splits::Splits;
+
+    fn millis(times: &[u64]) -> Vec<Duration> {
+        times.iter().map(|t| Duration::from_millis(*t)).collect()
+    }
+
+    fn history(course: &Course, runs: &[&[u64]]) -> RunHistory {
+        RunHistory {
+            runs: runs.iter().map(|times| RunRecord::new(course, "Racer", &millis(times), RunStatus::Finished, None)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_history_comparisons() {
+        let course = Course::from_points("test-course", &[[0.0, 0.0, 0.0], [50.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        let history = history(&course, &[&[0, 100, 300], &[0, 200, 300], &[0, 600, 700]]);
+        let fingerprint = course.fingerprint();
+        assert_eq!(Comparison::Average.splits(None, &history, &fingerprint, None), Some(vec![300, 133]));
+        assert_eq!(Comparison::Median.splits(None, &history, &fingerprint, None), Some(vec![200, 100]));
+        assert_eq!(Comparison::LatestRun.splits(None, &history, &fingerprint, None), Some(vec![600, 100]));
+        assert_eq!(Comparison::Average.splits(None, &history, "other layout", None), None);
+        assert_eq!(Comparison::PersonalBest.splits(None, &history, &fingerprint, None), None);
+    }
+
+    #[test]
+    fn test_deltas() {
+        let splits = vec![100, 200, 300];
+        let times = millis(&[0, 150, 300]);
+        assert_eq!(segment_delta(&splits, &times, 1), Some(50));
+        assert_eq!(segment_delta(&splits, &times, 2), Some(-50));
+        assert_eq!(segment_delta(&splits, &times, 3), None);
+        assert_eq!(projected_final(&splits, &times), Some(Duration::from_millis(600)));
+        assert_eq!(projected_final(&splits, &[]), None);
+    }
+
+    #[test]
+    fn test_live_projection() {
+        let splits = vec![100, 200, 300];
+        let times = millis(&[0, 150]);
+        // still within the comparison's time for the segment
+        assert_eq!(live_projection(&splits, &times, Duration::from_millis(250)), Some(Duration::from_millis(650)));
+        // 100ms slower than the comparison on the current segment so far
+        assert_eq!(live_projection(&splits, &times, Duration::from_millis(450)), Some(Duration::from_millis(750)));
+        assert_eq!(live_projection(&splits, &millis(&[0, 150, 300, 590]), Duration::from_secs(1)), Some(Duration::from_millis(590)));
+        assert_eq!(live_projection(&splits, &[], Duration::ZERO), None);
+
+        let lap = RaceLap {
+            pb_laptime: 600,
+            splits: Splits { pb: splits, best: vec![90, 200, 250] },
+        };
+        assert_eq!(possible_time_save(&lap), vec![10, 0, 50]);
+    }
+}
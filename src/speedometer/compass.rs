@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use super::guild_wars_handler::Position;
+
+/// Arrows pointing relative to the camera, going clockwise from straight ahead
+const ARROWS: [char; 8] = ['↑', '↗', '→', '↘', '↓', '↙', '←', '↖'];
+
+/// Degrees off the direction of the next checkpoint past which the racer is heading away from it
+const WRONG_WAY_ANGLE: f32 = 100f32;
+
+/// How long the racer has to keep heading away before being warned, so turning around a tight
+/// corner or sliding sideways doesn't set it off
+const WRONG_WAY_DELAY: Duration = Duration::from_millis(750);
+
+/// Where a target is relative to the way the racer is looking
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bearing {
+    /// Degrees to turn to face the target, positive to the right
+    pub angle: f32,
+    /// How far above the racer the target is, negative when below
+    pub height: f32,
+    /// Distance to the target on the map, ignoring height
+    pub distance: f32,
+}
+
+impl Bearing {
+    /// Arrow pointing at the target, with straight ahead pointing up
+    pub fn arrow(&self) -> char {
+        let sector = (self.angle.rem_euclid(360f32) / 45f32).round() as usize;
+        ARROWS[sector % 8]
+    }
+}
+
+/// Direction of a vector on the map, in degrees clockwise from north
+pub fn heading(vector: &Position) -> Option<f32> {
+    (vector[0] != 0f32 || vector[2] != 0f32).then(|| vector[0].atan2(vector[2]).to_degrees())
+}
+
+/// Turns a difference between two headings into the shortest turn, from -180 to 180 degrees
+pub fn relative_angle(from: f32, to: f32) -> f32 {
+    let angle = (to - from).rem_euclid(360f32);
+    if angle > 180f32 { angle - 360f32 } else { angle }
+}
+
+/// Where `target` is for a racer at `position` looking along `front`
+///
+/// Only the direction of `front` on the map matters, so looking up or down doesn't change the
+/// bearing. None if `front` points straight up or down, or the racer is on top of the target.
+pub fn bearing(position: &Position, front: &Position, target: &Position) -> Option<Bearing> {
+    let offset = [target[0] - position[0], target[1] - position[1], target[2] - position[2]];
+    Some(Bearing {
+        angle: relative_angle(heading(front)?, heading(&offset)?),
+        height: offset[1],
+        distance: (offset[0] * offset[0] + offset[2] * offset[2]).sqrt(),
+    })
+}
+
+/// Warns when the racer keeps moving away from the next checkpoint
+#[derive(Default, Debug)]
+pub struct WrongWayDetector {
+    heading_away_since: Option<Instant>,
+}
+
+impl WrongWayDetector {
+    /// Updates the detector with the direction the racer is moving in, in degrees clockwise from
+    /// north, and returns whether they're going the wrong way
+    ///
+    /// A racer who isn't moving is never going the wrong way.
+    pub fn update(&mut self, position: &Position, movement: Option<f32>, target: &Position, now: Instant) -> bool {
+        let offset = [target[0] - position[0], 0f32, target[2] - position[2]];
+        let away = match (movement, heading(&offset)) {
+            (Some(movement), Some(target)) => relative_angle(movement, target).abs() > WRONG_WAY_ANGLE,
+            _ => false,
+        };
+        self.heading_away_since = match (away, self.heading_away_since) {
+            (false, _) => None,
+            (true, since) => since.or(Some(now)),
+        };
+        self.is_wrong_way(now)
+    }
+
+    pub fn is_wrong_way(&self, now: Instant) -> bool {
+        self.heading_away_since.is_some_and(|since| now.duration_since(since) >= WRONG_WAY_DELAY)
+    }
+
+    pub fn reset(&mut self) {
+        self.heading_away_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearing() {
+        let facing_north = [0.0, 0.0, 1.0];
+        let ahead = bearing(&[0.0, 0.0, 0.0], &facing_north, &[0.0, 5.0, 10.0]).unwrap();
+        assert_eq!((ahead.angle, ahead.height, ahead.distance, ahead.arrow()), (0.0, 5.0, 10.0, '↑'));
+
+        let right = bearing(&[0.0, 0.0, 0.0], &facing_north, &[10.0, -2.0, 10.0]).unwrap();
+        assert!((right.angle - 45.0).abs() < 1e-4);
+        assert_eq!((right.height, right.arrow()), (-2.0, '↗'));
+
+        // facing east, a checkpoint to the north is a quarter turn to the left
+        let left = bearing(&[0.0, 0.0, 0.0], &[1.0, -0.5, 0.0], &[0.0, 0.0, 10.0]).unwrap();
+        assert!((left.angle + 90.0).abs() < 1e-4);
+        assert_eq!(left.arrow(), '←');
+
+        assert_eq!(bearing(&[0.0, 0.0, 0.0], &[0.0, 1.0, 0.0], &[0.0, 0.0, 10.0]), None);
+        assert_eq!(relative_angle(350.0, 10.0), 20.0);
+        assert_eq!(relative_angle(10.0, 350.0), -20.0);
+    }
+
+    #[test]
+    fn test_wrong_way() {
+        let mut detector = WrongWayDetector::default();
+        let start = Instant::now();
+        let (position, target) = ([0.0, 0.0, 0.0], [0.0, 0.0, 100.0]);
+        assert!(!detector.update(&position, Some(180.0), &target, start));
+        assert!(!detector.update(&position, Some(180.0), &target, start + Duration::from_millis(500)));
+        assert!(detector.update(&position, Some(180.0), &target, start + Duration::from_millis(800)));
+        // turning back towards the checkpoint clears the warning straight away
+        assert!(!detector.update(&position, Some(30.0), &target, start + Duration::from_millis(900)));
+        assert!(!detector.update(&position, None, &target, start + Duration::from_secs(2)));
+    }
+}
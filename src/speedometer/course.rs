@@ -3,9 +3,13 @@ use std::{fs::File, io::Write};
 
 use super::checkpoint::Stepname;
 use super::checkpoint::Checkpoint;
-use anyhow::Result;
+use super::util::euclidian_distance_3d;
+use anyhow::{bail, Result};
 use log;
 
+/// How far behind the start a derived reset checkpoint is placed
+const RESET_OFFSET: f32 = 60f32;
+
 /// Course is a series of numbered checkpoints with dedicated Start, Reset, and End checkpoints
 #[derive(Clone, Debug)]
 pub struct Course {
@@ -27,6 +31,25 @@ impl Course {
         let idx = self.checkpoints.len();
         self.checkpoints.push(Checkpoint { step: idx as i16, stepname: Stepname::Checkpoint, x, y, z, radius })
     }
+    /// Builds a course with a start at the first point, an end at the last point and a
+    /// checkpoint at every point in between
+    pub fn from_points(name: &str, points: &[[f32; 3]], radius: i32) -> Course {
+        let mut course = Course::new();
+        course.name = name.to_string();
+        for point in points {
+            course.push_cp(point[0], point[1], point[2], radius);
+        }
+        course.relabel();
+        course
+    }
+
+    /// Numbers the checkpoints in the order they appear in the course
+    pub fn renumber(&mut self) {
+        for (idx, cp) in self.checkpoints.iter_mut().enumerate() {
+            cp.step = idx as i16;
+        }
+    }
+
     pub fn add_reset(&mut self, x: f32, y: f32, z: f32, radius: i32) {
         self.reset = Some(Checkpoint {
             step: -1,
@@ -36,6 +59,111 @@ impl Course {
         });
     }
 
+    /// Gives the first checkpoint the Start name, the last one the End name and every other
+    /// one the Checkpoint name, then numbers them in order
+    pub fn relabel(&mut self) {
+        let last = self.checkpoints.len().saturating_sub(1);
+        for (idx, cp) in self.checkpoints.iter_mut().enumerate() {
+            cp.stepname = match idx {
+                0 => Stepname::Start,
+                idx if idx == last => Stepname::End,
+                _ => Stepname::Checkpoint,
+            };
+        }
+        self.renumber();
+    }
+
+    /// Places the reset behind the start, facing away from the first checkpoint
+    pub fn derive_reset(&mut self) {
+        let (Some(start), Some(next)) = (self.checkpoints.first(), self.checkpoints.get(1)) else {
+            self.reset = None;
+            return
+        };
+        let (dx, dz) = (next.x - start.x, next.z - start.z);
+        let length = (dx * dx + dz * dz).sqrt();
+        let (ux, uz) = if length > 0f32 { (dx / length, dz / length) } else { (0f32, 0f32) };
+        self.add_reset(start.x - ux * RESET_OFFSET, start.y, start.z - uz * RESET_OFFSET, start.radius);
+    }
+
+    /// The same course driven from the end back to the start
+    pub fn reversed(&self, name: &str) -> Course {
+        let mut course = self.clone();
+        course.name = name.to_string();
+        course.checkpoints.reverse();
+        course.relabel();
+        course.derive_reset();
+        course
+    }
+
+    /// The part of the course from checkpoint `from` to checkpoint `to`, inclusive
+    pub fn trimmed(&self, name: &str, from: usize, to: usize) -> Result<Course> {
+        if from >= to || to >= self.checkpoints.len() {
+            bail!("Can't trim checkpoints {} to {} out of a course with {} checkpoints", from, to, self.checkpoints.len());
+        }
+        let mut course = self.clone();
+        course.name = name.to_string();
+        course.checkpoints = self.checkpoints[from..=to].to_vec();
+        course.relabel();
+        if from > 0 {
+            course.derive_reset();
+        }
+        Ok(course)
+    }
+
+    /// This course followed by `other`, keeping this course's reset
+    ///
+    /// When `other` starts where this course ends, the two checkpoints are joined into one.
+    pub fn merged(&self, other: &Course, name: &str) -> Course {
+        let mut course = self.clone();
+        course.name = name.to_string();
+        let mut rest = other.checkpoints.iter().peekable();
+        if let (Some(end), Some(start)) = (course.checkpoints.last(), rest.peek()) {
+            if euclidian_distance_3d(&end.point(), &start.point()) < end.radius as f32 {
+                rest.next();
+            }
+        }
+        course.checkpoints.extend(rest.copied());
+        course.relabel();
+        course
+    }
+
+    /// Whether `position` is inside checkpoint `idx`
+    pub fn is_in_checkpoint(&self, idx: usize, position: &[f32; 3]) -> bool {
+        self.checkpoints.get(idx).is_some_and(|cp| euclidian_distance_3d(position, &cp.point()) < cp.radius as f32)
+    }
+
+    /// Checkpoint `next` if the racer at `position` is in a later checkpoint up to `last` without having collected it
+    ///
+    /// Being in the checkpoint just collected or in `next` itself never counts, so a finish on top of
+    /// the start or checkpoints that overlap don't look skipped.
+    pub fn skipped_checkpoint(&self, next: usize, last: usize, position: &[f32; 3]) -> Option<usize> {
+        if self.is_in_checkpoint(next, position) || next.checked_sub(1).is_some_and(|previous| self.is_in_checkpoint(previous, position)) {
+            return None
+        }
+        (next + 1..=last).any(|idx| self.is_in_checkpoint(idx, position)).then_some(next)
+    }
+
+    /// Whether `position` is inside the reset checkpoint, if the course has one
+    pub fn is_in_reset(&self, position: &[f32; 3]) -> bool {
+        self.reset.is_some_and(|cp| euclidian_distance_3d(position, &cp.point()) < cp.radius as f32)
+    }
+
+    /// Short hash of the checkpoints and reset, which changes whenever the course layout does
+    ///
+    /// FNV-1a is used rather than the standard library hasher so that fingerprints stay the
+    /// same between builds.
+    pub fn fingerprint(&self) -> String {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for cp in self.reset.iter().chain(self.checkpoints.iter()) {
+            let fields = [cp.step as u32, cp.stepname as u32, cp.x.to_bits(), cp.y.to_bits(), cp.z.to_bits(), cp.radius as u32];
+            for byte in fields.iter().flat_map(|field| field.to_le_bytes()) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        format!("{:016x}", hash)
+    }
+
     pub fn from_path(path: &String) -> Result<Course> {
         let mut reader = csv::Reader::from_path(&path)?;
         let filename = Path::new(path).file_stem().unwrap().to_string_lossy().to_string();
@@ -88,6 +216,70 @@ mod tests {
 
     use super::*;
 
+    fn xs(course: &Course) -> Vec<f32> {
+        course.checkpoints.iter().map(|cp| cp.x).collect()
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let course = Course::from_points("course", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        let mut moved = course.clone();
+        moved.checkpoints[1].x = 101.0;
+        let mut renamed = course.clone();
+        renamed.name = String::from("renamed");
+        assert_eq!(course.fingerprint().len(), 16);
+        assert_ne!(course.fingerprint(), moved.fingerprint());
+        assert_eq!(course.fingerprint(), renamed.fingerprint());
+    }
+
+    #[test]
+    fn test_skipped_checkpoint() {
+        let course = Course::from_points("test-course", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [200.0, 0.0, 0.0], [0.0, 0.0, 5.0]], 15);
+        assert_eq!(course.skipped_checkpoint(1, 3, &[200.0, 0.0, 0.0]), Some(1));
+        assert_eq!(course.skipped_checkpoint(1, 3, &[100.0, 0.0, 0.0]), None);
+        assert_eq!(course.skipped_checkpoint(1, 3, &[150.0, 0.0, 0.0]), None);
+        // the finish overlaps the start, which was just collected
+        assert_eq!(course.skipped_checkpoint(1, 3, &[0.0, 0.0, 2.0]), None);
+        // practicing up to checkpoint 1 doesn't care about the rest
+        assert_eq!(course.skipped_checkpoint(1, 1, &[200.0, 0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_reversed() {
+        let mut course = Course::from_points("forwards", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [200.0, 0.0, 0.0]], 15);
+        course.add_reset(-60.0, 0.0, 0.0, 15);
+        let reversed = course.reversed("backwards");
+        assert_eq!(xs(&reversed), vec![200.0, 100.0, 0.0]);
+        assert_eq!(reversed.checkpoints.iter().map(|cp| (cp.step, cp.stepname)).collect::<Vec<_>>(),
+            vec![(0, Stepname::Start), (1, Stepname::Checkpoint), (2, Stepname::End)]);
+        assert_eq!(reversed.reset.unwrap().point(), [260.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_trimmed() -> Result<()> {
+        let course = Course::from_points("full", &[[0.0, 0.0, 0.0], [0.0, 0.0, 100.0], [0.0, 0.0, 200.0], [0.0, 0.0, 300.0]], 15);
+        let trimmed = course.trimmed("middle", 1, 2)?;
+        assert_eq!(trimmed.checkpoints.iter().map(|cp| cp.z).collect::<Vec<f32>>(), vec![100.0, 200.0]);
+        assert_eq!(trimmed.checkpoints[0].stepname, Stepname::Start);
+        assert_eq!(trimmed.checkpoints[1].stepname, Stepname::End);
+        assert_eq!(trimmed.reset.unwrap().point(), [0.0, 0.0, 40.0]);
+        assert!(course.trimmed("empty", 2, 2).is_err());
+        assert!(course.trimmed("past the end", 2, 4).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged() {
+        let first = Course::from_points("first", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        let joined = Course::from_points("joined", &[[105.0, 0.0, 0.0], [200.0, 0.0, 0.0]], 15);
+        let apart = Course::from_points("apart", &[[150.0, 0.0, 0.0], [200.0, 0.0, 0.0]], 15);
+        assert_eq!(xs(&first.merged(&joined, "merged")), vec![0.0, 100.0, 200.0]);
+        let merged = first.merged(&apart, "merged");
+        assert_eq!(xs(&merged), vec![0.0, 100.0, 150.0, 200.0]);
+        assert_eq!(merged.checkpoints[3].stepname, Stepname::End);
+        assert_eq!(merged.checkpoints[3].step, 3);
+    }
+
     // #[test]
     // fn test_export_import() -> Result<()> {
     //     let path = String::from("/tmp/speedylemon-test-course.csv");
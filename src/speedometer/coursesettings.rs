@@ -0,0 +1,93 @@
+use std::{fmt::Display, fs::create_dir_all, path::Path, str::FromStr};
+
+use anyhow::{bail, Result, Context};
+use log;
+use serde::{Serialize, Deserialize};
+
+use super::util::{Exportable, Importable};
+
+/// What happens to a run when the racer reaches a checkpoint past one they didn't collect
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedCheckpointPolicy {
+    /// The run goes on once the racer goes back for the checkpoint they missed
+    #[default]
+    Recover,
+    /// The run is thrown out and the course restarted
+    Void,
+}
+
+impl Display for MissedCheckpointPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match *self {
+            Self::Recover => "recover",
+            Self::Void => "void",
+        })
+    }
+}
+
+impl FromStr for MissedCheckpointPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "recover" => Ok(Self::Recover),
+            "void" => Ok(Self::Void),
+            _ => bail!("Unknown missed checkpoint policy: {}", s),
+        }
+    }
+}
+
+/// Rules of a course that aren't part of its checkpoints
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CourseSettings {
+    pub missed_checkpoint: MissedCheckpointPolicy,
+}
+
+impl CourseSettings {
+    pub fn path(track: &str) -> String {
+        format!("data/course_settings/{}.toml", track)
+    }
+
+    pub fn load(track: &str) -> Result<CourseSettings> {
+        Ok(CourseSettings::import(&CourseSettings::path(track))?.unwrap_or_default())
+    }
+}
+
+impl Importable for CourseSettings {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing course settings from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let toml_str = std::fs::read_to_string(path).context("Failed to read course settings")?;
+        let settings = toml::from_str(&toml_str).context("Failed to parse course settings")?;
+        Ok(Some(settings))
+    }
+}
+
+impl Exportable for CourseSettings {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting course settings to {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create course settings directory")?;
+        let toml_str = toml::to_string(&self)?;
+        std::fs::write(path, toml_str)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_import() -> Result<()> {
+        let path = String::from("/tmp/speedylemon-test-course-settings.toml");
+        let settings = CourseSettings { missed_checkpoint: "void".parse()? };
+        settings.export(path.clone())?;
+        assert_eq!(CourseSettings::import(&path)?, Some(settings));
+        assert!("skip".parse::<MissedCheckpointPolicy>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+use std::{collections::{BTreeMap, HashSet}, fs::create_dir_all, path::Path, time::Instant};
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Duration, Utc};
+use log;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use super::checkpoint::Checkpoint;
+use super::course::Course;
+use super::guild_wars_handler::Position;
+use super::logcompare::course_progress;
+use super::racelog::RaceLogEntry;
+use super::util::{escape, euclidian_distance_3d, Exportable, Importable};
+
+pub const MAPS_PATH: &str = "data/maps.toml";
+
+/// Continent units per metre of Mumble position, since a continent unit is 24 inches
+const CONTINENT_UNITS_PER_METRE: f64 = 1f64 / (0.0254 * 24f64);
+
+/// GPX wants latitude and longitude, so continent coordinates are scaled down to degrees by this
+/// much, which keeps the whole continent within valid latitudes
+const CONTINENT_UNITS_PER_DEGREE: f64 = 2048f64;
+
+/// How long a map's transform has to hold still before it is remembered
+const STABLE_TRANSFORM_DURATION: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Continent units two samples of a map's origin can differ by and still count as the same
+const TRANSFORM_TOLERANCE: f64 = 1f64;
+
+/// Corners of the polygon drawn for each checkpoint
+const CIRCLE_VERTICES: usize = 32;
+
+/// Converts Mumble's map-local positions into continent coordinates
+///
+/// Mumble only reports the continent position of the player, through the compass data, so the
+/// map's origin is worked out from a single sample of the player's position in both systems.
+/// The compass `map_center` and `map_scale` aren't used: the centre follows the world map when
+/// it is panned, and the scale is the zoom of the compass or map on screen, so neither relates
+/// positions to the continent. Continent y grows to the south, like in the GW2 API.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ContinentTransform {
+    /// Continent coordinates of the map's local origin
+    pub origin: [f64; 2],
+}
+
+impl ContinentTransform {
+    /// Transform matching a player at `position` in the map and `continent` on the continent
+    pub fn from_sample(position: &Position, continent: [f32; 2]) -> ContinentTransform {
+        ContinentTransform {
+            origin: [
+                continent[0] as f64 - position[0] as f64 * CONTINENT_UNITS_PER_METRE,
+                continent[1] as f64 + position[2] as f64 * CONTINENT_UNITS_PER_METRE,
+            ],
+        }
+    }
+
+    pub fn apply(&self, position: &Position) -> [f64; 2] {
+        [
+            self.origin[0] + position[0] as f64 * CONTINENT_UNITS_PER_METRE,
+            self.origin[1] - position[2] as f64 * CONTINENT_UNITS_PER_METRE,
+        ]
+    }
+
+    /// Continent units between the origins of two transforms
+    fn distance(&self, other: &ContinentTransform) -> f64 {
+        (self.origin[0] - other.origin[0]).hypot(self.origin[1] - other.origin[1])
+    }
+
+    /// Length of `metres` in continent units
+    pub fn scale(&self, metres: f32) -> f64 {
+        metres as f64 * CONTINENT_UNITS_PER_METRE
+    }
+}
+
+/// Decides when a map's continent transform can be trusted enough to remember
+///
+/// Right after loading into a map, Mumble can report the new map alongside the previous map's
+/// positions for a moment, which gives an origin that is far off. A map's transform is only
+/// taken once it has held still for a while, and then only once.
+#[derive(Debug, Default)]
+pub struct TransformSampler {
+    candidate: Option<(u32, ContinentTransform, Instant)>,
+    recorded: HashSet<u32>,
+}
+
+impl TransformSampler {
+    /// Transform to remember for `map_id`, the first time it has matched itself for long enough
+    pub fn sample(&mut self, map_id: u32, transform: ContinentTransform, now: Instant) -> Option<ContinentTransform> {
+        if map_id == 0 || self.recorded.contains(&map_id) {
+            return None
+        }
+        match self.candidate {
+            Some((candidate_map, candidate, since)) if candidate_map == map_id && candidate.distance(&transform) <= TRANSFORM_TOLERANCE => {
+                if now.duration_since(since) < STABLE_TRANSFORM_DURATION {
+                    return None
+                }
+                self.candidate = None;
+                self.recorded.insert(map_id);
+                Some(transform)
+            },
+            _ => {
+                self.candidate = Some((map_id, transform, now));
+                None
+            },
+        }
+    }
+}
+
+/// Continent transforms of every map raced on, keyed by map id
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MapTransforms {
+    pub maps: BTreeMap<String, ContinentTransform>,
+}
+
+impl MapTransforms {
+    pub fn load() -> Result<MapTransforms> {
+        Ok(MapTransforms::import(&MAPS_PATH.to_string())?.unwrap_or_default())
+    }
+
+    pub fn get(&self, map_id: u32) -> Option<ContinentTransform> {
+        self.maps.get(&map_id.to_string()).copied()
+    }
+
+    /// Remembers the transform of a map so logs driven on it can be exported later
+    pub fn record(map_id: u32, transform: ContinentTransform) -> Result<()> {
+        let mut transforms = MapTransforms::load()?;
+        transforms.maps.insert(map_id.to_string(), transform);
+        transforms.export(MAPS_PATH.to_string())
+    }
+}
+
+impl Importable for MapTransforms {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing map transforms from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let toml_str = std::fs::read_to_string(path).context("Failed to read map transforms")?;
+        let transforms = toml::from_str(&toml_str).context("Failed to parse map transforms")?;
+        Ok(Some(transforms))
+    }
+}
+
+impl Exportable for MapTransforms {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting map transforms to {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create map transform directory")?;
+        let toml_str = toml::to_string(&self)?;
+        std::fs::write(path, toml_str)?;
+        Ok(())
+    }
+}
+
+/// A course, and optionally a run on it, as a GeoJSON FeatureCollection in continent coordinates
+///
+/// The run is a LineString, and every checkpoint and the reset are polygons the size of their radius.
+pub fn to_geojson(course: &Course, log: Option<&[RaceLogEntry]>, transform: &ContinentTransform) -> String {
+    let mut features: Vec<Value> = Vec::new();
+    if let Some(log) = log {
+        let path: Vec<[f64; 2]> = log.iter().map(|entry| transform.apply(&[entry.x, entry.y, entry.z])).collect();
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": path },
+            "properties": {
+                "kind": "run",
+                "course": course.name,
+                "time": log.last().map_or(0f64, |entry| entry.timestamp),
+            },
+        }));
+    }
+    let course_line: Vec<[f64; 2]> = course.checkpoints.iter().map(|cp| transform.apply(&cp.point())).collect();
+    features.push(json!({
+        "type": "Feature",
+        "geometry": { "type": "LineString", "coordinates": course_line },
+        "properties": { "kind": "course", "course": course.name },
+    }));
+    features.extend(course.checkpoints.iter().chain(course.reset.iter()).map(|cp| json!({
+        "type": "Feature",
+        "geometry": { "type": "Polygon", "coordinates": [circle(cp, transform)] },
+        "properties": { "kind": "checkpoint", "label": cp.label(), "step": cp.step, "radius": cp.radius },
+    })));
+    json!({ "type": "FeatureCollection", "features": features }).to_string()
+}
+
+/// A run as a GPX track, with continent coordinates scaled down to degrees
+///
+/// `start` is the moment the log's timestamps are counted from.
+pub fn to_gpx(name: &str, log: &[RaceLogEntry], transform: &ContinentTransform, start: DateTime<Utc>) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    lines.push("<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string());
+    lines.push("<gpx version=\"1.1\" creator=\"speedylemon\" xmlns=\"http://www.topografix.com/GPX/1/1\">".to_string());
+    lines.push("  <trk>".to_string());
+    lines.push(format!("    <name>{}</name>", escape(name)));
+    lines.push("    <trkseg>".to_string());
+    for entry in log {
+        let [x, y] = transform.apply(&[entry.x, entry.y, entry.z]);
+        let time = start + Duration::milliseconds((entry.timestamp * 1000f64) as i64);
+        lines.push(format!("      <trkpt lat=\"{:.8}\" lon=\"{:.8}\"><ele>{:.2}</ele><time>{}</time></trkpt>",
+            -y / CONTINENT_UNITS_PER_DEGREE, x / CONTINENT_UNITS_PER_DEGREE, entry.y, time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)));
+    }
+    lines.push("    </trkseg>".to_string());
+    lines.push("  </trk>".to_string());
+    lines.push("</gpx>".to_string());
+    lines.join("\n")
+}
+
+/// A run as CSV with one row per sample, in both coordinate systems and with how far along it is
+///
+/// DISTANCE is how far the racer has driven, PROGRESS how far along the course that got them.
+pub fn to_csv(course: &Course, log: &[RaceLogEntry], transform: &ContinentTransform) -> String {
+    let progress = course_progress(course, log);
+    let mut distance = 0f32;
+    let mut lines: Vec<String> = vec![String::from("TIME,X,Y,Z,CONTINENT_X,CONTINENT_Y,SPEED,DISTANCE,PROGRESS")];
+    for (idx, entry) in log.iter().enumerate() {
+        let position = [entry.x, entry.y, entry.z];
+        if idx > 0 {
+            distance += euclidian_distance_3d(&[log[idx - 1].x, log[idx - 1].y, log[idx - 1].z], &position);
+        }
+        let [cx, cy] = transform.apply(&position);
+        lines.push(format!("{:.3},{},{},{},{:.2},{:.2},{},{:.2},{:.2}",
+            entry.timestamp, entry.x, entry.y, entry.z, cx, cy, entry.speed, distance, progress.get(idx).copied().unwrap_or_default()));
+    }
+    lines.join("\n")
+}
+
+/// Closed ring of continent coordinates around a checkpoint
+fn circle(checkpoint: &Checkpoint, transform: &ContinentTransform) -> Vec<[f64; 2]> {
+    let [cx, cy] = transform.apply(&checkpoint.point());
+    let radius = transform.scale(checkpoint.radius as f32);
+    (0..=CIRCLE_VERTICES).map(|idx| {
+        let angle = std::f64::consts::TAU * idx as f64 / CIRCLE_VERTICES as f64;
+        [cx + radius * angle.cos(), cy + radius * angle.sin()]
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(x: f32, z: f32, timestamp: f64) -> RaceLogEntry {
+        RaceLogEntry::sample([x, 5.0, z], 10.0, timestamp)
+    }
+
+    #[test]
+    fn test_transform() {
+        let transform = ContinentTransform::from_sample(&[0.0, 0.0, 0.0], [1000.0, 2000.0]);
+        let [x, y] = transform.apply(&[0.6096, 0.0, 0.6096]);
+        // 0.6096 metres is 24 inches, one continent unit, and north is up
+        assert!((x - 1001.0).abs() < 1e-6);
+        assert!((y - 1999.0).abs() < 1e-6);
+
+        let moved = ContinentTransform::from_sample(&[0.6096, 0.0, 0.6096], [1001.0, 1999.0]);
+        assert!((moved.origin[0] - 1000.0).abs() < 1e-4 && (moved.origin[1] - 2000.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_sampler() {
+        let now = Instant::now();
+        let later = |millis: u64| now + std::time::Duration::from_millis(millis);
+        let stale = ContinentTransform { origin: [5000.0, 5000.0] };
+        let settled = ContinentTransform { origin: [1000.0, 2000.0] };
+        let mut sampler = TransformSampler::default();
+        assert_eq!(sampler.sample(0, settled, now), None);
+        assert_eq!(sampler.sample(15, stale, now), None);
+        assert_eq!(sampler.sample(15, settled, later(10)), None);
+        assert_eq!(sampler.sample(15, settled, later(500)), None);
+        assert_eq!(sampler.sample(15, settled, later(1010)), Some(settled));
+        assert_eq!(sampler.sample(15, settled, later(3000)), None);
+    }
+
+    #[test]
+    fn test_exports() -> Result<()> {
+        let course = Course::from_points("test-course", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        let log = vec![entry(0.0, 0.0, 0.0), entry(30.0, 40.0, 1.5), entry(100.0, 0.0, 3.0)];
+        let transform = ContinentTransform { origin: [0.0, 0.0] };
+
+        let geojson: Value = serde_json::from_str(&to_geojson(&course, Some(&log), &transform))?;
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 4);
+        assert_eq!(features[0]["geometry"]["coordinates"].as_array().unwrap().len(), 3);
+        assert_eq!(features[2]["properties"]["label"], "Start");
+        assert_eq!(features[2]["geometry"]["coordinates"][0].as_array().unwrap().len(), CIRCLE_VERTICES + 1);
+
+        let start = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")?.with_timezone(&Utc);
+        let gpx = to_gpx("test-course", &log, &transform, start);
+        assert_eq!(gpx.matches("<trkpt").count(), 3);
+        assert!(gpx.contains("<time>2024-05-01T12:00:01.500Z</time>"));
+
+        let csv = to_csv(&course, &log, &transform);
+        assert_eq!(csv.lines().nth(2), Some("1.500,30,5,40,49.21,-65.62,10,50.00,30.00"));
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use super::course::Course;
+use super::guild_wars_handler::Position;
+use super::util::{euclidian_distance_2d, euclidian_distance_3d, turn_angle};
+
+/// Average speed in map units per second that a clean lap tends to hold
+///
+/// This is a tuning default for the expected lap time, not a measured figure, and
+/// `course-report` takes another speed for courses it doesn't suit.
+pub const DEFAULT_AVERAGE_SPEED: f32 = 35f32;
+
+/// Points sampled along each segment when estimating the length of the racing line
+const SPLINE_SAMPLES: usize = 8;
+
+/// Measurements of a course's shape, taken from its checkpoints
+#[derive(Clone, Debug, PartialEq)]
+pub struct CourseStats {
+    /// Straight-line distance from each checkpoint to the next
+    pub segments: Vec<f32>,
+    /// Sum of the straight-line segment distances
+    pub straight_length: f32,
+    /// Length of a smooth line curving through every checkpoint, closer to what a racer drives
+    pub path_length: f32,
+    /// Total height climbed going from checkpoint to checkpoint
+    pub elevation_gain: f32,
+    /// Total height dropped going from checkpoint to checkpoint
+    pub elevation_loss: f32,
+    /// Checkpoint with the sharpest turn, and how many degrees the course turns there
+    pub tightest_turn: Option<(usize, f32)>,
+}
+
+impl CourseStats {
+    pub fn new(course: &Course) -> CourseStats {
+        let points: Vec<Position> = course.checkpoints.iter().map(|cp| cp.point()).collect();
+        let segments: Vec<f32> = points.windows(2).map(|w| euclidian_distance_3d(&w[0], &w[1])).collect();
+
+        let mut elevation_gain = 0f32;
+        let mut elevation_loss = 0f32;
+        for w in points.windows(2) {
+            let climb = w[1][1] - w[0][1];
+            if climb > 0f32 {
+                elevation_gain += climb;
+            } else {
+                elevation_loss -= climb;
+            }
+        }
+
+        let tightest_turn = points.windows(3).enumerate()
+            // checkpoints stacked on top of each other have no direction to turn from
+            .filter(|(_, w)| euclidian_distance_2d(&w[0], &w[1]) > 0f32 && euclidian_distance_2d(&w[1], &w[2]) > 0f32)
+            .map(|(idx, w)| (idx + 1, turn_angle(&w[0], &w[1], &w[2])))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        CourseStats {
+            straight_length: segments.iter().sum(),
+            path_length: spline_length(&points),
+            segments,
+            elevation_gain,
+            elevation_loss,
+            tightest_turn,
+        }
+    }
+
+    /// Time it takes to drive the estimated path at `speed` map units per second
+    pub fn expected_time(&self, speed: f32) -> Duration {
+        if speed <= 0f32 {
+            return Duration::ZERO
+        }
+        Duration::from_secs_f32(self.path_length / speed)
+    }
+}
+
+/// Length of a Catmull-Rom spline passing through every point
+fn spline_length(points: &[Position]) -> f32 {
+    let mut length = 0f32;
+    for idx in 0..points.len().saturating_sub(1) {
+        let p0 = points[idx.saturating_sub(1)];
+        let (p1, p2) = (points[idx], points[idx + 1]);
+        let p3 = points[usize::min(idx + 2, points.len() - 1)];
+        let mut previous = p1;
+        for step in 1..=SPLINE_SAMPLES {
+            let point = catmull_rom(&p0, &p1, &p2, &p3, step as f32 / SPLINE_SAMPLES as f32);
+            length += euclidian_distance_3d(&previous, &point);
+            previous = point;
+        }
+    }
+    length
+}
+
+fn catmull_rom(p0: &Position, p1: &Position, p2: &Position, p3: &Position, t: f32) -> Position {
+    let (t2, t3) = (t * t, t * t * t);
+    let mut point = [0f32; 3];
+    for axis in 0..3 {
+        point[axis] = 0.5 * (2f32 * p1[axis]
+            + (p2[axis] - p0[axis]) * t
+            + (2f32 * p0[axis] - 5f32 * p1[axis] + 4f32 * p2[axis] - p3[axis]) * t2
+            + (3f32 * p1[axis] - p0[axis] - 3f32 * p2[axis] + p3[axis]) * t3);
+    }
+    point
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_course_stats() {
+        let course = Course::from_points("square", &[
+            [0.0, 0.0, 0.0],
+            [30.0, 10.0, 0.0],
+            [30.0, 0.0, 40.0],
+            [30.0, 5.0, 80.0],
+        ], 15);
+        let stats = CourseStats::new(&course);
+        assert_eq!(stats.segments.len(), 3);
+        assert!((stats.segments[0] - 1000f32.sqrt()).abs() < 1e-4);
+        assert!((stats.straight_length - stats.segments.iter().sum::<f32>()).abs() < 1e-4);
+        assert_eq!(stats.elevation_gain, 15.0);
+        assert_eq!(stats.elevation_loss, 10.0);
+        assert_eq!(stats.tightest_turn.map(|(idx, angle)| (idx, angle.round())), Some((1, 90.0)));
+        // the smooth line bends outwards around the corner, so it can't be shorter than the straight one
+        assert!(stats.path_length >= stats.straight_length);
+    }
+
+    #[test]
+    fn test_expected_time() {
+        let course = Course::from_points("straight", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        let stats = CourseStats::new(&course);
+        assert!((stats.path_length - 100.0).abs() < 1e-3);
+        assert!((stats.expected_time(50.0).as_secs_f32() - 2.0).abs() < 1e-3);
+        assert_eq!(stats.expected_time(0.0), Duration::ZERO);
+    }
+}
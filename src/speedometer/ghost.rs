@@ -0,0 +1,145 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::Result;
+
+use super::guild_wars_handler::Position;
+use super::logstore::LogStore;
+use super::polyline::Polyline;
+use super::racelog::RaceLogEntry;
+use super::util::Importable;
+
+/// Segments of the ghost's path searched behind and ahead of the racer's last known spot
+const SEARCH_BEHIND: usize = 5;
+const SEARCH_AHEAD: usize = 30;
+
+/// How far off the searched part of the path the racer can be before the whole path is searched again
+const LOST_DISTANCE: f32 = 50f32;
+
+/// How the racer compares to the ghost at this moment
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GhostDelta {
+    /// Seconds behind the ghost at the racer's position on the path, negative when ahead
+    pub time: f64,
+    /// Units along the path the racer is ahead of the ghost, negative when behind
+    pub distance: f32,
+}
+
+/// Ghost replays a past run, usually the PB, so the racer can be compared against it anywhere on the course
+pub struct Ghost {
+    path: Polyline,
+    /// Seconds since the start at which the ghost reached each point of its path
+    times: Vec<f64>,
+    last_segment: usize,
+}
+
+impl Ghost {
+    /// Ghost following a race log, if it has enough of a path to follow
+    pub fn new(log: &[RaceLogEntry]) -> Option<Ghost> {
+        if log.len() < 2 {
+            return None
+        }
+        Some(Ghost {
+            path: Polyline::new(log.iter().map(|entry| [entry.x, entry.y, entry.z]).collect()),
+            times: log.iter().map(|entry| entry.timestamp).collect(),
+            last_segment: 0,
+        })
+    }
+
+    /// Ghost of the run saved for `track` with a lap time of `laptime` milliseconds
+    ///
+    /// Logs saved before the log store existed are named after their track and lap time.
+    pub fn load(track: &str, laptime: u64) -> Result<Option<Ghost>> {
+        let store = LogStore::load()?;
+        if let Some(stored) = store.find(track, laptime) {
+            return Ok(Ghost::new(&store.read(stored)?))
+        }
+        let path = format!("./data/logs/{}_{}.csv", track, laptime);
+        if !Path::new(&path).exists() {
+            return Ok(None)
+        }
+        let log = Vec::<RaceLogEntry>::import(&path)?.unwrap_or_default();
+        Ok(Ghost::new(&log))
+    }
+
+    /// Path the ghost drives
+    pub fn path(&self) -> &Polyline {
+        &self.path
+    }
+
+    /// Sends the ghost back to the start for a new run
+    pub fn reset(&mut self) {
+        self.last_segment = 0;
+    }
+
+    /// Compares the racer at `position`, `elapsed` after the start, against the ghost
+    pub fn delta(&mut self, position: &Position, elapsed: Duration) -> Option<GhostDelta> {
+        let from = self.last_segment.saturating_sub(SEARCH_BEHIND);
+        let mut projection = self.path.project_within(position, from, self.last_segment + SEARCH_AHEAD)?;
+        if projection.offset > LOST_DISTANCE {
+            projection = self.path.project(position)?;
+        }
+        self.last_segment = projection.segment;
+
+        let ghost_time = self.time_at(projection.segment, projection.distance_along);
+        Some(GhostDelta {
+            time: elapsed.as_secs_f64() - ghost_time,
+            distance: projection.distance_along - self.distance_at_time(elapsed.as_secs_f64()),
+        })
+    }
+
+    /// Time at which the ghost passed the point `distance` along its path, on segment `segment`
+    fn time_at(&self, segment: usize, distance: f32) -> f64 {
+        let Some(end) = self.times.get(segment + 1) else {
+            return self.times[segment]
+        };
+        let (start_distance, end_distance) = (self.path.distance_at(segment), self.path.distance_at(segment + 1));
+        let t = if end_distance > start_distance { ((distance - start_distance) / (end_distance - start_distance)) as f64 } else { 0f64 };
+        self.times[segment] + (end - self.times[segment]) * t
+    }
+
+    /// Distance along its path the ghost had covered `seconds` after the start
+    fn distance_at_time(&self, seconds: f64) -> f32 {
+        let idx = self.times.partition_point(|t| *t < seconds);
+        if idx == 0 {
+            return 0f32
+        }
+        if idx >= self.times.len() {
+            return self.path.length()
+        }
+        let (start, end) = (self.times[idx - 1], self.times[idx]);
+        let t = if end > start { ((seconds - start) / (end - start)) as f32 } else { 0f32 };
+        self.path.distance_at(idx - 1) + (self.path.distance_at(idx) - self.path.distance_at(idx - 1)) * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn straight_log(seconds: usize) -> Vec<RaceLogEntry> {
+        // drives 10 units every second along the x axis
+        (0..=seconds).map(|step| RaceLogEntry::sample([step as f32 * 10.0, 0.0, 0.0], 10.0, step as f64)).collect()
+    }
+
+    #[test]
+    fn test_delta() {
+        let mut ghost = Ghost::new(&straight_log(10)).unwrap();
+        let behind = ghost.delta(&[25.0, 0.0, 1.0], Duration::from_secs(3)).unwrap();
+        assert!((behind.time - 0.5).abs() < 1e-6);
+        assert!((behind.distance + 5.0).abs() < 1e-4);
+
+        let ahead = ghost.delta(&[45.0, 0.0, 0.0], Duration::from_secs(4)).unwrap();
+        assert!((ahead.time + 0.5).abs() < 1e-6);
+        assert!((ahead.distance - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_lost_racer() {
+        let mut ghost = Ghost::new(&straight_log(100)).unwrap();
+        ghost.delta(&[0.0, 0.0, 0.0], Duration::ZERO);
+        // far beyond the search window, as if the racer took a shortcut
+        let delta = ghost.delta(&[950.0, 0.0, 0.0], Duration::from_millis(94_500)).unwrap();
+        assert!((delta.time + 0.5).abs() < 1e-6);
+        assert!(Ghost::new(&straight_log(0)).is_none());
+    }
+}
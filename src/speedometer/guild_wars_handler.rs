@@ -32,22 +32,39 @@ struct GuildwarsContext {
 pub type Position = [f32; 3];
 
 pub struct GW2Data {
-    handler: MumbleLinkHandler,
+    /// Link to the game, which is only missing when nothing is read from Mumble at all
+    #[cfg_attr(not(target_family="windows"), allow(dead_code))]
+    handler: Option<MumbleLinkHandler>,
     pub racer: Racer,
     pub camera: Camera,
     pub map_id: u32,
+    /// Continent coordinates of the player, once the compass data is known
+    pub continent: Option<[f32; 2]>,
 }
 
 impl GW2Data {
     pub fn new() -> Result<GW2Data> {
         Ok(GW2Data {
-            handler: MumbleLinkHandler::new()?,
+            handler: Some(MumbleLinkHandler::new()?),
             racer: Racer::new(),
             camera: Camera::new(),
             map_id: 0u32,
+            continent: None,
         })
     }
 
+    /// Data that is never read from the game, for testing the race logic without Mumble
+    #[cfg(test)]
+    pub fn offline() -> GW2Data {
+        GW2Data {
+            handler: None,
+            racer: Racer::new(),
+            camera: Camera::new(),
+            map_id: 0u32,
+            continent: None,
+        }
+    }
+
     /// Initializes the Mumble Link Data
     /// 
     /// For some reason, in order for Guild Wars 2 to start sending data, a certain number of
@@ -55,9 +72,9 @@ impl GW2Data {
     #[cfg(target_family="windows")]
     pub fn init(&mut self) -> Result<()> {
         log::info!("Waiting for Guild Wars 2 Mumble data");
-        let mut data = self.handler.read().context(format!("unable to read GW2 data from mumble API"))?;
+        let mut data = self.handler.as_ref().context("Mumble Link isn't open")?.read().context(format!("unable to read GW2 data from mumble API"))?;
         while data.name != "Guild Wars 2" {
-            data = self.handler.read().context(format!("unable to read GW2 data from mumble API"))?;
+            data = self.handler.as_ref().context("Mumble Link isn't open")?.read().context(format!("unable to read GW2 data from mumble API"))?;
             std::thread::sleep(std::time::Duration::from_millis(500));
         }
         log::debug!("{}", &data.identity);
@@ -79,13 +96,18 @@ impl GW2Data {
     /// ```
     #[cfg(target_family="windows")]
     pub fn update(&mut self) -> Result<()> {
-        let data = self.handler.read().context(format!("unable to read GW2 data from mumble API"))?;
+        let data = self.handler.as_ref().context("Mumble Link isn't open")?.read().context(format!("unable to read GW2 data from mumble API"))?;
         self.racer.position = data.avatar.position;
+        self.racer.front = data.avatar.front;
         self.camera.position = data.camera.position;
+        self.camera.front = data.camera.front;
 
         let gw2_data = data.read_context_into_struct::<GuildwarsContext>();
 
         self.map_id = gw2_data.map_id;
+        // player_x and player_y are where the player is on the continent, unlike map_center_x
+        // and map_center_y, which move with the world map when it's panned
+        self.continent = Some([gw2_data.player_x, gw2_data.player_y]);
         Ok(())
     }
 
@@ -0,0 +1,322 @@
+use std::{collections::HashSet, fmt::Display, fs::create_dir_all, path::Path, time::Duration};
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Local};
+use serde::{Serialize, Deserialize};
+
+use super::course::Course;
+use super::logstore::{LogStore, StoredLog};
+use super::racelog::RaceLogEntry;
+use super::technique::{self, TechniqueSummary};
+use super::util::{Exportable, Importable};
+
+/// How an attempt at a course ended
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RunStatus {
+    /// Every checkpoint was collected
+    Finished,
+    /// The racer went back to the reset checkpoint or pressed reset
+    Reset,
+    /// The attempt was abandoned or voided, and can't count towards anything
+    Invalid,
+}
+
+impl Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match *self {
+            Self::Finished => "Finished",
+            Self::Reset => "Reset",
+            Self::Invalid => "Invalid",
+        })
+    }
+}
+
+impl std::str::FromStr for RunStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "finished" => Ok(Self::Finished),
+            "reset" => Ok(Self::Reset),
+            "invalid" => Ok(Self::Invalid),
+            _ => anyhow::bail!("Unknown run status: {}", s),
+        }
+    }
+}
+
+/// A single attempt at a course
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub date: DateTime<Local>,
+    pub character: String,
+    pub course: String,
+    /// Fingerprint of the course layout the attempt was driven on
+    pub fingerprint: String,
+    /// Milliseconds since the start at which each checkpoint was collected, starting with the start itself
+    pub checkpoint_times: Vec<u64>,
+    pub status: RunStatus,
+    pub log_path: Option<String>,
+    /// Milliseconds from the start until the attempt ended, which for a reset is after the last checkpoint
+    #[serde(default)]
+    pub elapsed: Option<u64>,
+    /// Techniques used over the whole attempt, for attempts with a race log
+    #[serde(default)]
+    pub techniques: Option<TechniqueSummary>,
+    /// Techniques used on each segment that was driven, ending at each checkpoint after the start
+    #[serde(default)]
+    pub segment_techniques: Vec<TechniqueSummary>,
+}
+
+impl RunRecord {
+    pub fn new(course: &Course, character: &str, checkpoint_times: &[Duration], status: RunStatus, log_path: Option<String>) -> RunRecord {
+        RunRecord {
+            date: Local::now(),
+            character: character.to_string(),
+            course: course.name.clone(),
+            fingerprint: course.fingerprint(),
+            checkpoint_times: checkpoint_times.iter().map(|t| t.as_millis() as u64).collect(),
+            status,
+            log_path,
+            elapsed: None,
+            techniques: None,
+            segment_techniques: Vec::new(),
+        }
+    }
+
+    pub fn with_elapsed(mut self, elapsed: Duration) -> Self {
+        self.elapsed = Some(elapsed.as_millis() as u64);
+        self
+    }
+
+    /// Adds what techniques the attempt used, from its race log
+    pub fn with_techniques(mut self, log: &[RaceLogEntry]) -> Self {
+        let (Some(first), Some(last)) = (log.first(), log.last()) else {
+            return self
+        };
+        let phases = technique::phases(log);
+        self.techniques = Some(TechniqueSummary::new(&phases, first.timestamp, last.timestamp));
+        self.segment_techniques = self.checkpoint_times.windows(2)
+            .map(|w| TechniqueSummary::new(&phases, w[0] as f64 / 1000f64, w[1] as f64 / 1000f64))
+            .collect();
+        self
+    }
+
+    /// Index of the last checkpoint collected, or None if the attempt never crossed the start
+    pub fn reached_checkpoint(&self) -> Option<usize> {
+        self.checkpoint_times.len().checked_sub(1)
+    }
+
+    /// Time from the start to the last checkpoint, for attempts that finished
+    pub fn laptime(&self) -> Option<u64> {
+        match self.status {
+            RunStatus::Finished => self.checkpoint_times.last().copied(),
+            _ => None,
+        }
+    }
+
+    /// Time taken between each pair of collected checkpoints
+    pub fn splits(&self) -> Vec<u64> {
+        self.checkpoint_times.windows(2).map(|w| w[1].saturating_sub(w[0])).collect()
+    }
+}
+
+/// Which runs to pick out of a RunHistory. Anything left as None matches every run
+#[derive(Default, Debug, Clone)]
+pub struct RunFilter {
+    pub status: Option<RunStatus>,
+    pub character: Option<String>,
+    pub fingerprint: Option<String>,
+    /// Only keep this many of the most recent matching runs
+    pub limit: Option<usize>,
+}
+
+/// Every attempt at a single course, oldest first
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunHistory {
+    pub runs: Vec<RunRecord>,
+}
+
+impl RunHistory {
+    pub fn path(track: &str) -> String {
+        format!("data/history/{}.toml", track)
+    }
+
+    /// Loads the history of a track, which is empty if it has never been raced
+    pub fn load(track: &str) -> Result<RunHistory> {
+        Ok(RunHistory::import(&RunHistory::path(track))?.unwrap_or_default())
+    }
+
+    /// Adds a run and saves the history of its track
+    pub fn append(&mut self, record: RunRecord) -> Result<()> {
+        let path = RunHistory::path(&record.course);
+        self.runs.push(record);
+        self.export(path)
+    }
+
+    /// Adds the runs from `other` that aren't already in this history, keeping runs sorted by date
+    ///
+    /// Runs are only compared to the second, since that's all some splits formats keep.
+    pub fn merge(&mut self, other: RunHistory) {
+        for run in other.runs {
+            let duplicate = self.runs.iter().any(|existing| existing.date.timestamp() == run.date.timestamp() && existing.checkpoint_times == run.checkpoint_times);
+            if !duplicate {
+                self.runs.push(run);
+            }
+        }
+        self.runs.sort_by_key(|run| run.date);
+    }
+
+    /// Forgets the race logs at `paths`, returning whether any run referred to one
+    ///
+    /// Paths are compared without the `.gz` of compressed logs, since runs keep the path the
+    /// log was first saved under.
+    pub fn forget_logs(&mut self, paths: &HashSet<String>) -> bool {
+        let mut forgotten = false;
+        for run in self.runs.iter_mut() {
+            if run.log_path.as_ref().is_some_and(|path| paths.contains(path.trim_end_matches(".gz"))) {
+                run.log_path = None;
+                forgotten = true;
+            }
+        }
+        forgotten
+    }
+
+    /// Forgets logs deleted from a log store in the histories of the courses they were driven on
+    pub fn forget_deleted_logs(store: &LogStore, removed: &[StoredLog]) -> Result<()> {
+        let courses: HashSet<&String> = removed.iter().map(|log| &log.course).collect();
+        for course in courses {
+            let paths: HashSet<String> = removed.iter()
+                .filter(|log| log.course == *course)
+                .map(|log| store.path(log).trim_end_matches(".gz").to_string())
+                .collect();
+            let mut history = RunHistory::load(course)?;
+            if history.forget_logs(&paths) {
+                history.export(RunHistory::path(course))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Matching runs, oldest first
+    pub fn query(&self, filter: &RunFilter) -> Vec<&RunRecord> {
+        let runs: Vec<&RunRecord> = self.runs.iter()
+            .filter(|run| filter.status.is_none_or(|status| run.status == status))
+            .filter(|run| filter.character.as_ref().is_none_or(|character| run.character == *character))
+            .filter(|run| filter.fingerprint.as_ref().is_none_or(|fingerprint| run.fingerprint == *fingerprint))
+            .collect();
+        let skip = filter.limit.map_or(0, |limit| runs.len().saturating_sub(limit));
+        runs[skip..].to_vec()
+    }
+}
+
+impl Importable for RunHistory {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing run history from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let toml_str = std::fs::read_to_string(path).context("Failed to read run history")?;
+        let history = toml::from_str(&toml_str).context("Failed to parse run history")?;
+        Ok(Some(history))
+    }
+}
+
+impl Exportable for RunHistory {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting run history to {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create run history directory")?;
+        let toml_str = toml::to_string(&self)?;
+        std::fs::write(path, toml_str)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(character: &str, times: &[u64], status: RunStatus) -> RunRecord {
+        let course = Course::from_points("test-course", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        let times: Vec<Duration> = times.iter().map(|t| Duration::from_millis(*t)).collect();
+        RunRecord::new(&course, character, &times, status, None)
+    }
+
+    #[test]
+    fn test_laptime_splits() {
+        let finished = record("Racer", &[0, 100, 250], RunStatus::Finished);
+        assert_eq!(finished.laptime(), Some(250));
+        assert_eq!(finished.splits(), vec![100, 150]);
+        assert_eq!(record("Racer", &[0, 100], RunStatus::Reset).laptime(), None);
+    }
+
+    #[test]
+    fn test_query() {
+        let history = RunHistory {
+            runs: vec![
+                record("Racer", &[0, 100], RunStatus::Finished),
+                record("Racer", &[0], RunStatus::Reset),
+                record("Other", &[0, 90], RunStatus::Finished),
+                record("Racer", &[0, 80], RunStatus::Finished),
+            ],
+        };
+        let finished = history.query(&RunFilter { status: Some(RunStatus::Finished), ..Default::default() });
+        assert_eq!(finished.len(), 3);
+        let latest = history.query(&RunFilter { character: Some(String::from("Racer")), limit: Some(2), ..Default::default() });
+        assert_eq!(latest.iter().map(|run| run.status).collect::<Vec<_>>(), vec![RunStatus::Reset, RunStatus::Finished]);
+    }
+
+    fn log() -> Vec<RaceLogEntry> {
+        [(0.0, 50.0), (0.1, 90.0), (0.2, 90.0), (0.25, 50.0)].iter()
+            .map(|(timestamp, speed)| RaceLogEntry::sample([0.0; 3], *speed, *timestamp))
+            .collect()
+    }
+
+    #[test]
+    fn test_with_techniques() {
+        let log = log();
+        let run = record("Racer", &[0, 100, 250], RunStatus::Finished).with_techniques(&log);
+        let techniques = run.techniques.unwrap();
+        assert_eq!((techniques.coasting.duration, techniques.boosting.duration), (100, 150));
+        assert_eq!(run.segment_techniques.len(), 2);
+        assert_eq!(run.segment_techniques[1].boosting.count, 1);
+        assert_eq!(record("Racer", &[0], RunStatus::Reset).with_techniques(&[]).techniques, None);
+    }
+
+    #[test]
+    fn test_forget_logs() {
+        let mut history = RunHistory {
+            runs: vec![
+                RunRecord { log_path: Some(String::from("data/logs/T/1.csv")), ..record("Racer", &[0, 100], RunStatus::Finished) },
+                RunRecord { log_path: Some(String::from("data/logs/T/2.csv")), ..record("Racer", &[0, 90], RunStatus::Finished) },
+            ],
+        };
+        assert!(history.forget_logs(&HashSet::from([String::from("data/logs/T/1.csv")])));
+        assert_eq!(history.runs[0].log_path, None);
+        assert_eq!(history.runs[1].log_path.as_deref(), Some("data/logs/T/2.csv"));
+        assert!(!history.forget_logs(&HashSet::from([String::from("data/logs/T/1.csv")])));
+    }
+
+    #[test]
+    fn test_merge() {
+        let first = record("Racer", &[0, 100], RunStatus::Finished);
+        let second = RunRecord { date: first.date + chrono::Duration::seconds(5), ..record("Racer", &[0], RunStatus::Reset) };
+        let mut history = RunHistory { runs: vec![second.clone()] };
+        history.merge(RunHistory { runs: vec![first.clone(), second.clone()] });
+        assert_eq!(history.runs, vec![first, second]);
+    }
+
+    #[test]
+    fn test_export_import() -> Result<()> {
+        let path = String::from("/tmp/speedylemon-test-history.toml");
+        let history = RunHistory {
+            runs: vec![
+                record("Racer", &[0, 100, 250], RunStatus::Finished).with_techniques(&log()),
+                RunRecord { log_path: Some(String::from("data/logs/test.csv")), ..record("Racer", &[0], RunStatus::Reset) },
+            ],
+        };
+        history.export(path.clone())?;
+        let imported = RunHistory::import(&path)?.unwrap();
+        assert_eq!(history, imported);
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use std::{fs::create_dir_all, io::Write, net::{TcpStream, ToSocketAddrs}, path::Path, sync::{atomic::{AtomicBool, Ordering}, mpsc::{channel, Sender}, Arc}, thread, time::{Duration, Instant}};
+
+use anyhow::{Result, Context};
+use log;
+use serde::{Serialize, Deserialize};
+
+use super::util::{Exportable, Importable};
+
+pub const SETTINGS_PATH: &str = "data/livesplit.toml";
+
+/// How long to wait for LiveSplit to accept a connection before giving up until the next retry
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Where to find the LiveSplit Server component, and how often to look for it
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveSplitSettings {
+    pub enabled: bool,
+    pub address: String,
+    /// Seconds to wait before trying to reach LiveSplit again after losing the connection
+    pub reconnect_interval: u64,
+}
+
+impl Default for LiveSplitSettings {
+    fn default() -> Self {
+        LiveSplitSettings {
+            enabled: false,
+            address: String::from("localhost:16834"),
+            reconnect_interval: 5,
+        }
+    }
+}
+
+impl LiveSplitSettings {
+    pub fn load() -> Result<LiveSplitSettings> {
+        Ok(LiveSplitSettings::import(&SETTINGS_PATH.to_string())?.unwrap_or_default())
+    }
+}
+
+impl Importable for LiveSplitSettings {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing LiveSplit settings from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let toml_str = std::fs::read_to_string(path).context("Failed to read LiveSplit settings")?;
+        let settings = toml::from_str(&toml_str).context("Failed to parse LiveSplit settings")?;
+        Ok(Some(settings))
+    }
+}
+
+impl Exportable for LiveSplitSettings {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting LiveSplit settings to {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create settings directory")?;
+        let toml_str = toml::to_string(&self)?;
+        std::fs::write(path, toml_str)?;
+        Ok(())
+    }
+}
+
+/// LiveSplitClient drives a LiveSplit timer through the LiveSplit Server text protocol
+///
+/// LiveSplit's game time is paused for the whole run and set from our own checkpoint times
+/// before every split, so both timers agree exactly. Commands are handed to a worker thread
+/// that owns the connection, so resolving and connecting never hold up the race loop. They are
+/// dropped rather than queued while LiveSplit can't be reached, and the connection is retried in
+/// the background of later commands.
+pub struct LiveSplitClient {
+    commands: Sender<String>,
+    connected: Arc<AtomicBool>,
+}
+
+impl LiveSplitClient {
+    pub fn new(settings: LiveSplitSettings) -> LiveSplitClient {
+        let (commands, receiver) = channel::<String>();
+        let mut connection = Connection::new(settings);
+        let connected = connection.connected.clone();
+        // the worker stops once the client is dropped and the channel closes
+        thread::spawn(move || {
+            for command in receiver {
+                connection.send(&command);
+            }
+        });
+        LiveSplitClient {
+            commands,
+            connected,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub fn start_timer(&mut self) {
+        self.send("starttimer");
+        self.send("initgametime");
+        self.send("pausegametime");
+    }
+
+    /// Splits with LiveSplit's game time set to `time` since the start
+    pub fn split(&mut self, time: Duration) {
+        self.set_game_time(time);
+        self.send("split");
+    }
+
+    pub fn reset(&mut self) {
+        self.send("reset");
+    }
+
+    pub fn set_game_time(&mut self, time: Duration) {
+        self.send(&format!("setgametime {}", format_game_time(time)));
+    }
+
+    /// Queues a single command for the worker to send
+    pub fn send(&mut self, command: &str) {
+        if self.commands.send(command.to_string()).is_err() {
+            log::warn!("LiveSplit worker stopped, dropping {}", command);
+        }
+    }
+}
+
+/// Connection to LiveSplit Server, owned by the worker thread of a LiveSplitClient
+struct Connection {
+    settings: LiveSplitSettings,
+    stream: Option<TcpStream>,
+    last_attempt: Option<Instant>,
+    connected: Arc<AtomicBool>,
+}
+
+impl Connection {
+    fn new(settings: LiveSplitSettings) -> Connection {
+        Connection {
+            settings,
+            stream: None,
+            last_attempt: None,
+            connected: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Sends a single command, connecting first if needed. Returns whether it was sent
+    fn send(&mut self, command: &str) -> bool {
+        if self.stream.is_none() && !self.connect() {
+            return false
+        }
+        let stream = self.stream.as_mut().unwrap();
+        if let Err(e) = stream.write_all(format!("{}\r\n", command).as_bytes()) {
+            log::warn!("Lost connection to LiveSplit: {}", e);
+            self.stream = None;
+            self.connected.store(false, Ordering::Relaxed);
+            return false
+        }
+        true
+    }
+
+    fn connect(&mut self) -> bool {
+        let interval = Duration::from_secs(self.settings.reconnect_interval);
+        if self.last_attempt.is_some_and(|last| last.elapsed() < interval) {
+            return false
+        }
+        self.last_attempt = Some(Instant::now());
+        let Some(address) = self.settings.address.to_socket_addrs().ok().and_then(|mut addresses| addresses.next()) else {
+            log::warn!("Could not resolve LiveSplit address {}", self.settings.address);
+            return false
+        };
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                log::info!("Connected to LiveSplit at {}", self.settings.address);
+                let _ = stream.set_nodelay(true);
+                let _ = stream.set_write_timeout(Some(CONNECT_TIMEOUT));
+                self.stream = Some(stream);
+                self.connected.store(true, Ordering::Relaxed);
+                true
+            },
+            Err(e) => {
+                log::warn!("Could not connect to LiveSplit at {}: {}", self.settings.address, e);
+                false
+            },
+        }
+    }
+}
+
+/// Formats a time the way LiveSplit Server reads it, e.g. 1:03.123
+fn format_game_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    fn settings(address: String) -> LiveSplitSettings {
+        LiveSplitSettings { enabled: true, address, reconnect_interval: 0 }
+    }
+
+    fn read_lines(listener: &TcpListener, count: usize) -> Vec<String> {
+        let (stream, _) = listener.accept().unwrap();
+        BufReader::new(stream).lines().take(count).map(|line| line.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_commands() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = LiveSplitClient::new(settings(listener.local_addr().unwrap().to_string()));
+        client.start_timer();
+        client.split(Duration::from_millis(63_123));
+        client.reset();
+        assert_eq!(read_lines(&listener, 6), vec![
+            "starttimer", "initgametime", "pausegametime", "setgametime 1:03.123", "split", "reset",
+        ]);
+        assert!(client.is_connected());
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut connection = Connection::new(settings(address.to_string()));
+        assert!(!connection.send("split"));
+        assert!(!connection.connected.load(Ordering::Relaxed));
+
+        let listener = TcpListener::bind(address).unwrap();
+        assert!(connection.send("split"));
+        assert_eq!(read_lines(&listener, 1), vec!["split"]);
+    }
+
+    #[test]
+    fn test_reconnect_interval() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut connection = Connection::new(LiveSplitSettings { reconnect_interval: 60, ..settings(address.to_string()) });
+        assert!(!connection.send("split"));
+        let _listener = TcpListener::bind(address).unwrap();
+        // LiveSplit is back, but the client shouldn't look for it again until the interval has passed
+        assert!(!connection.send("split"));
+    }
+}
@@ -0,0 +1,183 @@
+use super::course::Course;
+use super::guild_wars_handler::Position;
+use super::polyline::Polyline;
+use super::racelog::RaceLogEntry;
+
+/// Course segments searched behind and ahead of the last one a log was projected onto
+const SEARCH_WINDOW: usize = 2;
+
+/// How two runs compare at one point along the course
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlignedSample {
+    /// Distance along the course from the start
+    pub progress: f32,
+    /// Seconds the second run took longer than the first to get here, negative when faster
+    pub time_delta: f64,
+    /// Speed of the second run minus the speed of the first
+    pub speed_delta: f32,
+    /// Sideways distance from the first run's line to the second's, positive to the right
+    pub line_offset: f32,
+}
+
+/// A race log sample placed along the course
+#[derive(Clone, Copy, Debug)]
+struct Placed {
+    progress: f32,
+    time: f64,
+    speed: f32,
+    lateral: f32,
+}
+
+/// Lines two race logs up by how far along the course each sample was, rather than when it was taken
+///
+/// Samples are compared every `step` units from the start until the shorter of the two runs
+/// stops. Each run's clock is counted from the moment it left the start of the course, so time
+/// spent waiting there before the run doesn't count.
+pub fn align_logs(course: &Course, first: &[RaceLogEntry], second: &[RaceLogEntry], step: f32) -> Vec<AlignedSample> {
+    let line = course_line(course);
+    let (first, second) = (place(&line, first), place(&line, second));
+    let (Some(first_end), Some(second_end)) = (first.last(), second.last()) else {
+        return Vec::new()
+    };
+    if step <= 0f32 {
+        return Vec::new()
+    }
+    let (first_start, second_start) = (departure(&first), departure(&second));
+    let end = f32::min(first_end.progress, second_end.progress);
+
+    let mut samples: Vec<AlignedSample> = Vec::new();
+    let mut progress = 0f32;
+    while progress <= end {
+        let (a, b) = (at(&first, progress), at(&second, progress));
+        samples.push(AlignedSample {
+            progress,
+            time_delta: (b.time - second_start) - (a.time - first_start),
+            speed_delta: b.speed - a.speed,
+            line_offset: b.lateral - a.lateral,
+        });
+        progress += step;
+    }
+    samples
+}
+
+/// How far along the course each sample of a log is, never going backwards
+pub fn course_progress(course: &Course, log: &[RaceLogEntry]) -> Vec<f32> {
+    place(&course_line(course), log).iter().map(|placed| placed.progress).collect()
+}
+
+/// Aligned samples as CSV, one row per sample
+pub fn to_csv(samples: &[AlignedSample]) -> String {
+    let mut lines: Vec<String> = vec![String::from("PROGRESS,TIME_DELTA,SPEED_DELTA,LINE_OFFSET")];
+    lines.extend(samples.iter().map(|s| format!("{:.1},{:.3},{:.2},{:.2}", s.progress, s.time_delta, s.speed_delta, s.line_offset)));
+    lines.join("\n")
+}
+
+fn course_line(course: &Course) -> Polyline {
+    Polyline::new(course.checkpoints.iter().map(|cp| cp.point()).collect())
+}
+
+/// Projects every sample of a log onto the course, never letting progress go backwards
+fn place(line: &Polyline, log: &[RaceLogEntry]) -> Vec<Placed> {
+    let mut placed: Vec<Placed> = Vec::new();
+    let mut segment = 0usize;
+    for entry in log {
+        let position = [entry.x, entry.y, entry.z];
+        let Some(projection) = line.project_within(&position, segment.saturating_sub(SEARCH_WINDOW), segment + SEARCH_WINDOW + 1) else {
+            continue
+        };
+        segment = projection.segment;
+        let progress = placed.last().map_or(projection.distance_along, |last| f32::max(last.progress, projection.distance_along));
+        placed.push(Placed {
+            progress,
+            time: entry.timestamp,
+            speed: entry.speed,
+            lateral: side(line, segment, &position) * projection.offset,
+        });
+    }
+    placed
+}
+
+/// 1 if `position` is to the right of the course's direction on `segment`, -1 if it's to the left
+///
+/// The map has x to the east and z to the north, so turning right is clockwise and a position
+/// on the right gives a negative cross product.
+fn side(line: &Polyline, segment: usize, position: &Position) -> f32 {
+    let Some(end) = line.points.get(segment + 1) else {
+        return 1f32
+    };
+    let start = line.points[segment];
+    let cross = (end[0] - start[0]) * (position[2] - start[2]) - (end[2] - start[2]) * (position[0] - start[0]);
+    if cross <= 0f32 { 1f32 } else { -1f32 }
+}
+
+/// Time of the last sample before the run started moving along the course
+fn departure(placed: &[Placed]) -> f64 {
+    placed[placed.partition_point(|p| p.progress <= placed[0].progress) - 1].time
+}
+
+/// Run's state when it first reached `progress`, interpolated between samples
+fn at(placed: &[Placed], progress: f32) -> Placed {
+    let idx = placed.partition_point(|p| p.progress < progress);
+    if idx == 0 {
+        return placed[0]
+    }
+    if idx >= placed.len() {
+        return placed[placed.len() - 1]
+    }
+    let (a, b) = (placed[idx - 1], placed[idx]);
+    let t = if b.progress > a.progress { (progress - a.progress) / (b.progress - a.progress) } else { 0f32 };
+    Placed {
+        progress,
+        time: a.time + (b.time - a.time) * t as f64,
+        speed: a.speed + (b.speed - a.speed) * t,
+        lateral: a.lateral + (b.lateral - a.lateral) * t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(points: &[(f32, f32, f64, f32)]) -> Vec<RaceLogEntry> {
+        points.iter().map(|(x, z, timestamp, speed)| RaceLogEntry::sample([*x, 0.0, *z], *speed, *timestamp)).collect()
+    }
+
+    #[test]
+    fn test_align_logs() {
+        let course = Course::from_points("straight", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        // the second run drives half as fast, two units north of the first, which is on its left
+        let first = log(&[(0.0, 0.0, 8.0, 0.0), (0.0, 0.0, 10.0, 20.0), (100.0, 0.0, 15.0, 20.0)]);
+        let second = log(&[(0.0, 2.0, 0.0, 10.0), (100.0, 2.0, 10.0, 10.0)]);
+        let samples = align_logs(&course, &first, &second, 50.0);
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[1].progress, 50.0);
+        assert!((samples[1].time_delta - 2.5).abs() < 1e-6);
+        assert!((samples[2].time_delta - 5.0).abs() < 1e-6);
+        assert_eq!(samples[1].speed_delta, -10.0);
+        assert_eq!(samples[1].line_offset, -2.0);
+
+        let csv = to_csv(&samples);
+        assert_eq!(csv.lines().nth(2), Some("50.0,2.500,-10.00,-2.00"));
+    }
+
+    #[test]
+    fn test_progress_never_goes_backwards() {
+        let line = Polyline::new(vec![[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]]);
+        let placed = place(&line, &log(&[(0.0, 0.0, 0.0, 0.0), (40.0, 0.0, 1.0, 0.0), (30.0, 0.0, 2.0, 0.0)]));
+        assert_eq!(placed.iter().map(|p| p.progress).collect::<Vec<f32>>(), vec![0.0, 40.0, 40.0]);
+    }
+
+    #[test]
+    fn test_side() {
+        let line = Polyline::new(vec![[0.0, 0.0, 0.0], [0.0, 0.0, 100.0]]);
+        // heading north, east is on the right
+        assert_eq!(side(&line, 0, &[5.0, 0.0, 50.0]), 1.0);
+        assert_eq!(side(&line, 0, &[-5.0, 0.0, 50.0]), -1.0);
+    }
+
+    #[test]
+    fn test_course_without_checkpoints() {
+        let course = Course::from_points("empty", &[], 15);
+        assert!(course_progress(&course, &log(&[(0.0, 0.0, 0.0, 0.0)])).is_empty());
+    }
+}
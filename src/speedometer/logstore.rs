@@ -0,0 +1,316 @@
+use std::{collections::HashSet, fs::{create_dir_all, File}, io::Write, path::{Path, PathBuf}};
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Local};
+use flate2::{write::GzEncoder, Compression};
+use log;
+use serde::{Serialize, Deserialize};
+
+use super::racelog::{write_racelog, RaceLogEntry};
+use super::util::{Exportable, Importable};
+
+pub const LOG_DIR: &str = "data/logs";
+pub const SETTINGS_PATH: &str = "data/logstore.toml";
+
+/// Name of the index file kept at the root of a log store
+const INDEX_FILE: &str = "index.toml";
+
+/// Which race logs to keep, and when to compress them. Anything left as None is never done
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Number of most recent logs to keep for each course, besides the PBs
+    pub keep_last: Option<usize>,
+    /// Days after which logs are gzipped
+    pub compress_after_days: Option<i64>,
+}
+
+impl RetentionPolicy {
+    pub fn load() -> Result<RetentionPolicy> {
+        Ok(RetentionPolicy::import(&SETTINGS_PATH.to_string())?.unwrap_or_default())
+    }
+}
+
+impl Importable for RetentionPolicy {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing log retention policy from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let toml_str = std::fs::read_to_string(path).context("Failed to read log retention policy")?;
+        let policy = toml::from_str(&toml_str).context("Failed to parse log retention policy")?;
+        Ok(Some(policy))
+    }
+}
+
+impl Exportable for RetentionPolicy {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting log retention policy to {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create settings directory")?;
+        let toml_str = toml::to_string(&self)?;
+        std::fs::write(path, toml_str)?;
+        Ok(())
+    }
+}
+
+/// A race log kept in a LogStore
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct StoredLog {
+    pub id: String,
+    pub course: String,
+    pub character: String,
+    pub date: DateTime<Local>,
+    /// Milliseconds from the start to the last checkpoint collected
+    pub laptime: u64,
+    /// Whether the run collected every checkpoint and can count towards splits, rather than
+    /// being reset or voided on the way
+    pub valid: bool,
+    /// Location of the log, relative to the store
+    pub file: String,
+}
+
+impl StoredLog {
+    pub fn is_compressed(&self) -> bool {
+        self.file.ends_with(".gz")
+    }
+}
+
+/// Race logs saved under unique IDs, with an index of what each one holds
+///
+/// Logs live in `<store>/<course>/<id>.csv`, or `.csv.gz` once compressed, so runs with the
+/// same lap time no longer overwrite each other.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogStore {
+    #[serde(skip)]
+    dir: PathBuf,
+    pub logs: Vec<StoredLog>,
+}
+
+impl LogStore {
+    /// Opens the store in `data/logs`
+    pub fn load() -> Result<LogStore> {
+        LogStore::open(LOG_DIR)
+    }
+
+    /// Opens the store kept in `dir`, which is empty if nothing has been saved there yet
+    pub fn open(dir: impl AsRef<Path>) -> Result<LogStore> {
+        let dir = dir.as_ref().to_path_buf();
+        let index = dir.join(INDEX_FILE);
+        let mut store = LogStore::import(&index.to_string_lossy().to_string())?.unwrap_or_default();
+        store.dir = dir;
+        Ok(store)
+    }
+
+    /// Where a stored log is on disk
+    pub fn path(&self, log: &StoredLog) -> String {
+        self.dir.join(&log.file).to_string_lossy().to_string()
+    }
+
+    /// Saves a race log under a new ID and adds it to the index
+    pub fn save(&mut self, course: &str, character: &str, log: &[RaceLogEntry], laptime: u64, valid: bool) -> Result<StoredLog> {
+        let date = Local::now();
+        let base = date.format("%Y%m%d-%H%M%S%3f").to_string();
+        let mut id = base.clone();
+        let mut suffix = 1;
+        while self.get(&id).is_some() {
+            suffix += 1;
+            id = format!("{}-{}", base, suffix);
+        }
+        let stored = StoredLog {
+            file: format!("{}/{}.csv", course, id),
+            id,
+            course: course.to_string(),
+            character: character.to_string(),
+            date,
+            laptime,
+            valid,
+        };
+        write_racelog(log, self.path(&stored)).context("Failed to export race log")?;
+        self.logs.push(stored.clone());
+        self.save_index()?;
+        Ok(stored)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&StoredLog> {
+        self.logs.iter().find(|log| log.id == id)
+    }
+
+    /// Logs of a course, or every log if no course is given, oldest first
+    pub fn query(&self, course: Option<&str>) -> Vec<&StoredLog> {
+        self.logs.iter().filter(|log| course.is_none_or(|course| log.course == course)).collect()
+    }
+
+    /// The most recent valid log of a course with the given lap time
+    pub fn find(&self, course: &str, laptime: u64) -> Option<&StoredLog> {
+        self.logs.iter().rev().find(|log| log.course == course && log.laptime == laptime && log.valid)
+    }
+
+    /// Reads a stored log back, decompressing it if needed
+    pub fn read(&self, log: &StoredLog) -> Result<Vec<RaceLogEntry>> {
+        let path = self.path(log);
+        Vec::<RaceLogEntry>::import(&path)?.context(format!("No race log at {}", path))
+    }
+
+    /// Deletes every log that is neither a PB nor one of the `keep_last` most recent of its course
+    ///
+    /// PBs are kept for each character on each course. Returns the logs that were removed.
+    pub fn prune(&mut self, keep_last: usize) -> Result<Vec<StoredLog>> {
+        let mut kept: HashSet<String> = HashSet::new();
+        let courses: HashSet<String> = self.logs.iter().map(|log| log.course.clone()).collect();
+        for course in courses {
+            let logs = self.query(Some(&course));
+            kept.extend(logs.iter().rev().take(keep_last).map(|log| log.id.clone()));
+            let characters: HashSet<&String> = logs.iter().map(|log| &log.character).collect();
+            for character in characters {
+                let pb = logs.iter()
+                    .filter(|log| log.valid && log.character == *character)
+                    .min_by_key(|log| log.laptime);
+                kept.extend(pb.map(|log| log.id.clone()));
+            }
+        }
+
+        let (keep, removed): (Vec<StoredLog>, Vec<StoredLog>) = self.logs.drain(..).partition(|log| kept.contains(&log.id));
+        self.logs = keep;
+        for log in removed.iter() {
+            let path = self.path(log);
+            if Path::new(&path).exists() {
+                std::fs::remove_file(&path).context(format!("Failed to delete {}", path))?;
+            }
+        }
+        self.save_index()?;
+        Ok(removed)
+    }
+
+    /// Gzips every log saved more than `days` days before `now`, returning how many were compressed
+    /// and the logs dropped from the index because their files were deleted by hand
+    pub fn compress(&mut self, days: i64, now: DateTime<Local>) -> Result<(usize, Vec<StoredLog>)> {
+        let mut compressed = 0;
+        let mut missing: HashSet<String> = HashSet::new();
+        for idx in 0..self.logs.len() {
+            let log = &self.logs[idx];
+            if log.is_compressed() || now.signed_duration_since(log.date).num_days() < days {
+                continue
+            }
+            let path = self.path(log);
+            if !Path::new(&path).exists() {
+                log::warn!("Race log {} is missing, removing it from the index", path);
+                missing.insert(log.id.clone());
+                continue
+            }
+            let data = std::fs::read(&path).context(format!("Failed to read {}", path))?;
+            let mut encoder = GzEncoder::new(File::create(format!("{}.gz", path))?, Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish().context("Failed to compress race log")?;
+            std::fs::remove_file(&path)?;
+            self.logs[idx].file.push_str(".gz");
+            compressed += 1;
+        }
+        let (dropped, kept) = std::mem::take(&mut self.logs).into_iter().partition(|log| missing.contains(&log.id));
+        self.logs = kept;
+        self.save_index()?;
+        Ok((compressed, dropped))
+    }
+
+    /// Prunes and compresses logs as the policy asks, returning the logs removed, the logs dropped
+    /// because their files were missing and how many were compressed
+    pub fn apply(&mut self, policy: &RetentionPolicy) -> Result<(Vec<StoredLog>, Vec<StoredLog>, usize)> {
+        // the latest log is never pruned, since it was usually just saved and may still be uploaded
+        let removed = match policy.keep_last {
+            Some(keep_last) => self.prune(keep_last.max(1))?,
+            None => Vec::new(),
+        };
+        let (compressed, missing) = match policy.compress_after_days {
+            Some(days) => self.compress(days, Local::now())?,
+            None => (0, Vec::new()),
+        };
+        Ok((removed, missing, compressed))
+    }
+
+    fn save_index(&self) -> Result<()> {
+        self.export(self.dir.join(INDEX_FILE).to_string_lossy().to_string())
+    }
+}
+
+impl Importable for LogStore {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing race log index from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let toml_str = std::fs::read_to_string(path).context("Failed to read race log index")?;
+        let store = toml::from_str(&toml_str).context("Failed to parse race log index")?;
+        Ok(Some(store))
+    }
+}
+
+impl Exportable for LogStore {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting race log index to {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create race log directory")?;
+        let toml_str = toml::to_string(&self)?;
+        std::fs::write(path, toml_str)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: f64) -> RaceLogEntry {
+        RaceLogEntry::sample([0.0; 3], 0.0, timestamp)
+    }
+
+    fn store() -> Result<LogStore> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let time = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        LogStore::open(format!("/tmp/speedylemon_dev_logstore_{}", time.as_nanos()))
+    }
+
+    #[test]
+    fn test_unique_ids() -> Result<()> {
+        let mut store = store()?;
+        let log = vec![entry(0.0), entry(1.0)];
+        let first = store.save("T", "Racer", &log, 1000, true)?;
+        let second = store.save("T", "Racer", &log, 1000, true)?;
+        assert_ne!(first.id, second.id);
+        assert_eq!(store.find("T", 1000), Some(&second));
+
+        let reopened = LogStore::open(&store.dir)?;
+        assert_eq!(reopened.logs, store.logs);
+        assert_eq!(reopened.read(&first)?, log);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_and_compress() -> Result<()> {
+        let mut store = store()?;
+        let log = vec![entry(0.0)];
+        for (course, character, laptime) in [("T", "A", 900), ("T", "B", 1200), ("T", "A", 1000), ("T", "B", 1100), ("T", "A", 950), ("U", "A", 500)] {
+            store.save(course, character, &log, laptime, true)?;
+        }
+        let removed: Vec<u64> = store.prune(1)?.iter().map(|log| log.laptime).collect();
+        assert_eq!(removed, vec![1200, 1000]);
+        let kept: Vec<u64> = store.query(None).iter().map(|log| log.laptime).collect();
+        assert_eq!(kept, vec![900, 1100, 950, 500]);
+
+        assert_eq!(store.compress(0, Local::now())?, (4, Vec::new()));
+        let pb = store.find("T", 900).unwrap().clone();
+        assert!(pb.is_compressed());
+        assert_eq!(store.read(&pb)?, log);
+        assert_eq!(store.compress(0, Local::now())?, (0, Vec::new()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_missing_log() -> Result<()> {
+        let mut store = store()?;
+        let log = vec![entry(0.0)];
+        let deleted = store.save("T", "Racer", &log, 900, true)?;
+        store.save("T", "Racer", &log, 1000, false)?;
+        std::fs::remove_file(store.path(&deleted))?;
+        assert_eq!(store.compress(0, Local::now())?, (1, vec![deleted]));
+        assert_eq!(store.query(None).iter().map(|log| log.laptime).collect::<Vec<u64>>(), vec![1000]);
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use std::{fs::create_dir_all, path::Path, time::Duration};
+
+use anyhow::{Result, Context, bail};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use log;
+
+use super::course::Course;
+use super::history::{RunHistory, RunRecord, RunStatus};
+use super::splits::{RaceLap, Splits};
+use super::util::{escape, Exportable, Importable};
+
+const GAME_NAME: &str = "Guild Wars 2";
+
+/// Date format LiveSplit uses for attempts, always in UTC
+const DATE_FORMAT: &str = "%m/%d/%Y %H:%M:%S";
+
+/// A LiveSplit splits file, holding the PB, gold splits and every attempt at a course
+#[derive(PartialEq, Debug, Clone)]
+pub struct LiveSplitRun {
+    pub category: String,
+    pub attempts: Vec<Attempt>,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Attempt {
+    pub id: u32,
+    pub started: Option<DateTime<Local>>,
+    pub ended: Option<DateTime<Local>>,
+    /// Final time in milliseconds, only set for attempts that finished
+    pub time: Option<u64>,
+}
+
+/// A LiveSplit segment, which is the stretch of course leading up to a checkpoint
+#[derive(PartialEq, Debug, Clone)]
+pub struct Segment {
+    pub name: String,
+    /// Milliseconds from the start to the end of this segment in the PB
+    pub pb_time: Option<u64>,
+    /// Fastest this segment has ever been driven, in milliseconds
+    pub best_segment: Option<u64>,
+    /// Time in milliseconds each attempt took on this segment, by attempt id
+    pub history: Vec<(u32, u64)>,
+}
+
+impl LiveSplitRun {
+    /// Builds a splits file out of a course's local PB and run history
+    ///
+    /// Segments are named after the checkpoint they end at. Attempts that never got past the
+    /// start are left out, as LiveSplit has nothing to show for them, and so are attempts driven
+    /// on an older layout of the course.
+    pub fn new(course: &Course, lap: Option<&RaceLap>, history: &RunHistory) -> LiveSplitRun {
+        let mut segments: Vec<Segment> = course.checkpoints.iter().skip(1).enumerate().map(|(idx, cp)| Segment {
+            name: cp.label(),
+            pb_time: lap.and_then(|lap| lap.splits.pb.get(..=idx)).map(|splits| splits.iter().sum()),
+            best_segment: lap.and_then(|lap| lap.splits.best.get(idx)).copied(),
+            history: Vec::new(),
+        }).collect();
+
+        let mut attempts: Vec<Attempt> = Vec::new();
+        let fingerprint = course.fingerprint();
+        for run in history.runs.iter().filter(|run| run.fingerprint == fingerprint && run.checkpoint_times.len() > 1) {
+            let id = attempts.len() as u32 + 1;
+            let elapsed = run.elapsed.or(run.checkpoint_times.last().copied()).unwrap_or(0);
+            attempts.push(Attempt {
+                id,
+                started: Some(run.date - chrono::Duration::milliseconds(elapsed as i64)),
+                ended: Some(run.date),
+                time: run.laptime(),
+            });
+            for (segment, split) in segments.iter_mut().zip(run.splits()) {
+                segment.history.push((id, split));
+            }
+        }
+
+        LiveSplitRun {
+            category: course.name.clone(),
+            attempts,
+            segments,
+        }
+    }
+
+    /// Local PB and gold splits, if the file has a complete PB
+    pub fn race_lap(&self) -> Option<RaceLap> {
+        let cumulative: Vec<u64> = self.segments.iter().map(|segment| segment.pb_time).collect::<Option<Vec<u64>>>()?;
+        let pb: Vec<u64> = cumulative.iter().enumerate()
+            .map(|(idx, time)| time.saturating_sub(if idx > 0 { cumulative[idx - 1] } else { 0 }))
+            .collect();
+        let best = self.segments.iter().zip(pb.iter())
+            .map(|(segment, split)| segment.best_segment.map_or(*split, |best| u64::min(best, *split)))
+            .collect();
+        Some(RaceLap {
+            pb_laptime: *cumulative.last()?,
+            splits: Splits { pb, best },
+        })
+    }
+
+    /// Every attempt as a run on `course`, in the order they were made
+    ///
+    /// LiveSplit doesn't know which character drove an attempt, so every run is given to `character`.
+    pub fn run_history(&self, course: &Course, character: &str) -> RunHistory {
+        let runs = self.attempts.iter().map(|attempt| {
+            let mut checkpoint_times = vec![Duration::ZERO];
+            for segment in self.segments.iter() {
+                let Some((_, split)) = segment.history.iter().find(|(id, _)| *id == attempt.id) else {
+                    break
+                };
+                checkpoint_times.push(*checkpoint_times.last().unwrap() + Duration::from_millis(*split));
+            }
+            let status = if attempt.time.is_some() { RunStatus::Finished } else { RunStatus::Reset };
+            RunRecord {
+                date: attempt.ended.or(attempt.started).unwrap_or_else(Local::now),
+                ..RunRecord::new(course, character, &checkpoint_times, status, None)
+            }
+        }).collect();
+        RunHistory { runs }
+    }
+
+    pub fn to_lss(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        lines.push("<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string());
+        lines.push("<Run version=\"1.7.0\">".to_string());
+        lines.push("  <GameIcon />".to_string());
+        lines.push(format!("  <GameName>{}</GameName>", GAME_NAME));
+        lines.push(format!("  <CategoryName>{}</CategoryName>", escape(&self.category)));
+        lines.push("  <Offset>00:00:00</Offset>".to_string());
+        lines.push(format!("  <AttemptCount>{}</AttemptCount>", self.attempts.len()));
+        lines.push("  <AttemptHistory>".to_string());
+        for attempt in self.attempts.iter() {
+            let mut attributes = format!("id=\"{}\"", attempt.id);
+            if let Some(started) = attempt.started {
+                attributes.push_str(&format!(" started=\"{}\" isStartedSynced=\"True\"", started.with_timezone(&Utc).format(DATE_FORMAT)));
+            }
+            if let Some(ended) = attempt.ended {
+                attributes.push_str(&format!(" ended=\"{}\" isEndedSynced=\"True\"", ended.with_timezone(&Utc).format(DATE_FORMAT)));
+            }
+            match attempt.time {
+                Some(time) => lines.push(format!("    <Attempt {}>\n      <RealTime>{}</RealTime>\n    </Attempt>", attributes, format_time(time))),
+                None => lines.push(format!("    <Attempt {} />", attributes)),
+            }
+        }
+        lines.push("  </AttemptHistory>".to_string());
+        lines.push("  <Segments>".to_string());
+        for segment in self.segments.iter() {
+            lines.push("    <Segment>".to_string());
+            lines.push(format!("      <Name>{}</Name>", escape(&segment.name)));
+            lines.push("      <Icon />".to_string());
+            lines.push("      <SplitTimes>".to_string());
+            match segment.pb_time {
+                Some(time) => lines.push(format!("        <SplitTime name=\"Personal Best\">\n          <RealTime>{}</RealTime>\n        </SplitTime>", format_time(time))),
+                None => lines.push("        <SplitTime name=\"Personal Best\" />".to_string()),
+            }
+            lines.push("      </SplitTimes>".to_string());
+            match segment.best_segment {
+                Some(time) => lines.push(format!("      <BestSegmentTime>\n        <RealTime>{}</RealTime>\n      </BestSegmentTime>", format_time(time))),
+                None => lines.push("      <BestSegmentTime />".to_string()),
+            }
+            lines.push("      <SegmentHistory>".to_string());
+            for (id, time) in segment.history.iter() {
+                lines.push(format!("        <Time id=\"{}\">\n          <RealTime>{}</RealTime>\n        </Time>", id, format_time(*time)));
+            }
+            lines.push("      </SegmentHistory>".to_string());
+            lines.push("    </Segment>".to_string());
+        }
+        lines.push("  </Segments>".to_string());
+        lines.push("  <AutoSplitterSettings />".to_string());
+        lines.push("</Run>".to_string());
+        lines.join("\n")
+    }
+
+    pub fn from_lss(text: &str) -> Result<LiveSplitRun> {
+        let document = roxmltree::Document::parse(text).context("Failed to parse splits xml")?;
+        let root = document.root_element();
+        if !root.has_tag_name("Run") {
+            bail!("Not a LiveSplit splits file");
+        }
+
+        let attempts = child(root, "AttemptHistory").map(|history| history.children().filter(|n| n.has_tag_name("Attempt")).map(|attempt| {
+            Ok(Attempt {
+                id: attempt.attribute("id").context("Attempt is missing its id")?.parse().context("Invalid attempt id")?,
+                started: attempt.attribute("started").map(parse_date).transpose()?,
+                ended: attempt.attribute("ended").map(parse_date).transpose()?,
+                time: real_time(attempt)?,
+            })
+        }).collect::<Result<Vec<Attempt>>>()).transpose()?.unwrap_or_default();
+
+        let segments = child(root, "Segments").map(|segments| segments.children().filter(|n| n.has_tag_name("Segment")).map(|segment| {
+            let pb_time = child(segment, "SplitTimes")
+                .and_then(|times| times.children().find(|n| n.has_tag_name("SplitTime") && n.attribute("name") == Some("Personal Best")))
+                .map(real_time).transpose()?.flatten();
+            let history = child(segment, "SegmentHistory").map(|history| history.children().filter(|n| n.has_tag_name("Time")).filter_map(|time| {
+                let id = time.attribute("id").and_then(|id| id.parse().ok());
+                match (id, real_time(time)) {
+                    (Some(id), Ok(Some(time))) => Some(Ok((id, time))),
+                    (_, Err(e)) => Some(Err(e)),
+                    // skipped segments and LiveSplit's negative ids for cleared history have no time to keep
+                    _ => None,
+                }
+            }).collect::<Result<Vec<(u32, u64)>>>()).transpose()?.unwrap_or_default();
+            Ok(Segment {
+                name: child(segment, "Name").and_then(|name| name.text()).unwrap_or_default().to_string(),
+                pb_time,
+                best_segment: child(segment, "BestSegmentTime").map(real_time).transpose()?.flatten(),
+                history,
+            })
+        }).collect::<Result<Vec<Segment>>>()).transpose()?.unwrap_or_default();
+
+        Ok(LiveSplitRun {
+            category: child(root, "CategoryName").and_then(|name| name.text()).unwrap_or_default().to_string(),
+            attempts,
+            segments,
+        })
+    }
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+/// Real time held by an element, in milliseconds
+fn real_time(node: roxmltree::Node) -> Result<Option<u64>> {
+    child(node, "RealTime").and_then(|time| time.text()).map(parse_time).transpose()
+}
+
+/// Formats milliseconds the way LiveSplit writes times, e.g. 00:01:03.1230000
+fn format_time(millis: u64) -> String {
+    format!("{:02}:{:02}:{:02}.{:03}0000", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+/// Reads a LiveSplit time into milliseconds. Hours and days are optional, so 1:03.123 is also accepted
+fn parse_time(text: &str) -> Result<u64> {
+    let (days, clock) = match text.split_once('.') {
+        Some((days, rest)) if rest.contains(':') => (days.parse::<u64>().context(format!("Invalid time: {}", text))?, rest),
+        _ => (0, text),
+    };
+    let mut seconds = 0f64;
+    for part in clock.split(':') {
+        seconds = seconds * 60f64 + part.parse::<f64>().context(format!("Invalid time: {}", text))?;
+    }
+    Ok(days * 86_400_000 + (seconds * 1000f64).round() as u64)
+}
+
+fn parse_date(text: &str) -> Result<DateTime<Local>> {
+    let date = NaiveDateTime::parse_from_str(text, DATE_FORMAT).context(format!("Invalid attempt date: {}", text))?;
+    Ok(Utc.from_utc_datetime(&date).with_timezone(&Local))
+}
+
+impl Importable for LiveSplitRun {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing LiveSplit splits from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let text = std::fs::read_to_string(path).context("Failed to read splits file")?;
+        Ok(Some(LiveSplitRun::from_lss(&text)?))
+    }
+}
+
+impl Exportable for LiveSplitRun {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting LiveSplit splits to {}", path);
+        if let Some(parent) = Path::new(&path).parent() {
+            create_dir_all(parent).context("Failed to create splits directory")?;
+        }
+        std::fs::write(path, self.to_lss()).context("Failed to write splits file")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course() -> Course {
+        Course::from_points("Test & Course", &[[0.0, 0.0, 0.0], [50.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15)
+    }
+
+    fn millis(times: &[u64]) -> Vec<Duration> {
+        times.iter().map(|t| Duration::from_millis(*t)).collect()
+    }
+
+    #[test]
+    fn test_times() -> Result<()> {
+        assert_eq!(format_time(3_723_456), "01:02:03.4560000");
+        assert_eq!(parse_time("01:02:03.4560000")?, 3_723_456);
+        assert_eq!(parse_time("1:03.5")?, 63_500);
+        assert_eq!(parse_time("1.00:00:01")?, 86_401_000);
+        assert!(parse_time("soon").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let course = course();
+        let lap = RaceLap::new(&millis(&[0, 1000, 2500]));
+        let history = RunHistory {
+            runs: vec![
+                RunRecord::new(&course, "Racer", &millis(&[0, 1200]), RunStatus::Reset, None),
+                RunRecord::new(&course, "Racer", &millis(&[0, 1000, 2500]), RunStatus::Finished, None),
+                // reset right after the start, so there's nothing for LiveSplit to show
+                RunRecord::new(&course, "Racer", &millis(&[0]), RunStatus::Reset, None),
+                // driven before the course was moved, so it isn't part of this layout's history
+                RunRecord::new(&Course::from_points("Test & Course", &[[0.0, 0.0, 0.0], [60.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15), "Racer", &millis(&[0, 900, 2000]), RunStatus::Finished, None),
+            ],
+        };
+        let run = LiveSplitRun::new(&course, Some(&lap), &history);
+        assert_eq!(run.segments[0].name, "Checkpoint 1");
+        assert_eq!(run.segments[1].pb_time, Some(2500));
+
+        let imported = LiveSplitRun::from_lss(&run.to_lss())?;
+        assert_eq!(imported.category, "Test & Course");
+        assert_eq!(imported.segments, run.segments);
+        assert_eq!(imported.attempts.len(), 2);
+        assert_eq!(imported.attempts[1].time, Some(2500));
+        assert_eq!(imported.race_lap(), Some(lap));
+
+        let runs = imported.run_history(&course, "Racer").runs;
+        assert_eq!(runs.iter().map(|run| run.status).collect::<Vec<_>>(), vec![RunStatus::Reset, RunStatus::Finished]);
+        assert_eq!(runs[0].checkpoint_times, history.runs[0].checkpoint_times);
+        assert_eq!(runs[1].checkpoint_times, history.runs[1].checkpoint_times);
+        assert_eq!(runs[1].date.timestamp(), history.runs[1].date.timestamp());
+        Ok(())
+    }
+}
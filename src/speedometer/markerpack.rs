@@ -0,0 +1,115 @@
+use std::{fs::create_dir_all, path::Path};
+
+use anyhow::{Result, Context};
+use log;
+
+use super::checkpoint::{Checkpoint, DEFAULT_RADIUS};
+use super::course::Course;
+use super::trail::Trail;
+use super::util::{escape, Exportable};
+
+/// Root category that every exported course is nested under
+const ROOT_CATEGORY: &str = "speedylemon";
+
+/// MarkerPack turns a Course into TacO/Blish HUD overlay data
+///
+/// Each course gets its own category containing a marker for every checkpoint and the reset,
+/// and optionally a trail of the racing line taken from a race log.
+pub struct MarkerPack<'a> {
+    pub course: &'a Course,
+    pub map_id: u32,
+    pub trail: Option<Trail>,
+}
+
+impl<'a> MarkerPack<'a> {
+    pub fn new(course: &'a Course, map_id: u32) -> MarkerPack<'a> {
+        MarkerPack {
+            course,
+            map_id,
+            trail: None,
+        }
+    }
+
+    pub fn with_trail(mut self, trail: Trail) -> Self {
+        self.trail = Some(trail);
+        self
+    }
+
+    /// Category name of the course, lowercase with anything TacO can't handle replaced
+    pub fn category(&self) -> String {
+        self.course.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect()
+    }
+
+    /// File name used for the xml and trail files, without an extension
+    pub fn file_stem(&self) -> String {
+        Path::new(&self.course.name).file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or(self.category())
+    }
+
+    pub fn to_xml(&self) -> String {
+        let category = self.category();
+        let mut lines: Vec<String> = Vec::new();
+        lines.push("<OverlayData>".to_string());
+        lines.push(format!("  <MarkerCategory name=\"{}\" DisplayName=\"SpeedyLemon\">", ROOT_CATEGORY));
+        lines.push(format!("    <MarkerCategory name=\"{}\" DisplayName=\"{}\">", category, escape(&self.course.name)));
+        lines.push("      <MarkerCategory name=\"checkpoints\" DisplayName=\"Checkpoints\" />".to_string());
+        lines.push("      <MarkerCategory name=\"reset\" DisplayName=\"Reset\" />".to_string());
+        if self.trail.is_some() {
+            lines.push("      <MarkerCategory name=\"trail\" DisplayName=\"Racing Line\" />".to_string());
+        }
+        lines.push("    </MarkerCategory>".to_string());
+        lines.push("  </MarkerCategory>".to_string());
+        lines.push("  <POIs>".to_string());
+        for cp in self.course.checkpoints.iter() {
+            lines.push(self.poi(cp, &format!("{}.{}.checkpoints", ROOT_CATEGORY, category)));
+        }
+        if let Some(reset) = &self.course.reset {
+            lines.push(self.poi(reset, &format!("{}.{}.reset", ROOT_CATEGORY, category)));
+        }
+        if self.trail.is_some() {
+            lines.push(format!("    <Trail type=\"{}.{}.trail\" trailData=\"{}.trl\" />", ROOT_CATEGORY, category, escape(&self.file_stem())));
+        }
+        lines.push("  </POIs>".to_string());
+        lines.push("</OverlayData>".to_string());
+        lines.join("\n")
+    }
+
+    fn poi(&self, cp: &Checkpoint, category: &str) -> String {
+        format!("    <POI MapID=\"{}\" xpos=\"{}\" ypos=\"{}\" zpos=\"{}\" type=\"{}\" triggerRange=\"{}\" iconSize=\"{:.3}\" info=\"{}\" />",
+            self.map_id, cp.x, cp.y, cp.z, category, cp.radius, cp.radius as f32 / DEFAULT_RADIUS as f32, cp.label())
+    }
+}
+
+impl Exportable for MarkerPack<'_> {
+    /// Writes the marker xml to `path` and the trail, if any, next to it
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting marker pack to path: {}", path);
+        let parent = Path::new(&path).parent().unwrap();
+        create_dir_all(parent).context("Failed to create marker pack directory")?;
+        std::fs::write(&path, self.to_xml()).context("Failed to write marker xml")?;
+        if let Some(trail) = &self.trail {
+            trail.export(parent.join(format!("{}.trl", self.file_stem())).to_string_lossy().to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_xml() {
+        let mut course = Course::new();
+        course.name = String::from("custom_courses/Rock & Roll");
+        course.push_cp(1.0, 2.0, 3.0, 30);
+        course.add_reset(4.0, 5.0, 6.0, 15);
+        let xml = MarkerPack::new(&course, 1206).with_trail(Trail { map_id: 1206, points: Vec::new() }).to_xml();
+
+        assert!(xml.contains("<MarkerCategory name=\"custom_courses_rock___roll\" DisplayName=\"custom_courses/Rock &amp; Roll\">"));
+        assert!(xml.contains("xpos=\"1\" ypos=\"2\" zpos=\"3\" type=\"speedylemon.custom_courses_rock___roll.checkpoints\" triggerRange=\"30\" iconSize=\"2.000\""));
+        assert!(xml.contains("type=\"speedylemon.custom_courses_rock___roll.reset\""));
+        assert!(xml.contains("trailData=\"Rock &amp; Roll.trl\""));
+    }
+}
@@ -1,21 +1,47 @@
 use std::{collections::VecDeque, path::Path, time::{Duration, Instant}};
 
 use beetlerank::BeetleRank;
-use checkpoint::Checkpoint;
+use compass::{Bearing, WrongWayDetector};
 use course::Course;
+use coursesettings::{CourseSettings, MissedCheckpointPolicy};
 use csv::Reader;
-use guild_wars_handler::GW2Data;
-use util::{euclidian_distance_2d, euclidian_distance_3d};
+use guild_wars_handler::{GW2Data, Position};
+use livesplit::LiveSplitClient;
+use polyline::Polyline;
+use trail::Trail;
+use util::{euclidian_distance_2d, euclidian_distance_3d, Importable};
 
 use anyhow::Result;
 
+/// How long the missed checkpoint warning stays up after a run is voided
+const MISSED_WARNING_DURATION: Duration = Duration::from_secs(4);
+
+pub mod analysis;
+pub mod autoplace;
 pub mod camera;
 pub mod checkpoint;
+pub mod comparison;
+pub mod compass;
 pub mod course;
+pub mod coursesettings;
+pub mod geoexport;
+pub mod geometry;
+pub mod ghost;
 pub mod guild_wars_handler;
+pub mod history;
+pub mod livesplit;
+pub mod logcompare;
+pub mod logstore;
+pub mod lss;
+pub mod markerpack;
+pub mod polyline;
 pub mod racelog;
 pub mod racer;
+pub mod retime;
 pub mod splits;
+pub mod statistics;
+pub mod technique;
+pub mod trail;
 pub mod util;
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -40,15 +66,94 @@ impl TimePosition {
     }
 }
 
+/// Where a run from checkpoint `first` to `last` stands when `current` is the next checkpoint to collect
+pub fn race_state(current: usize, (first, last): (usize, usize)) -> RaceState {
+    match current {
+        cp if cp <= first => RaceState::WaitingToStart,
+        cp if cp <= last => RaceState::Racing,
+        _ => RaceState::Finished,
+    }
+}
+
+/// Something the racer did to a run by moving, reported in the order it happened
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum RunEvent {
+    /// Went through the reset checkpoint, which restarts the run
+    Reset,
+    /// Collected the next checkpoint
+    Collect,
+    /// Reached a later checkpoint while this one was still uncollected
+    Skip(usize),
+}
+
+/// Where a run stands in the course, which is all the speedometer and retiming a log need to
+/// agree on to score a run the same way
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct RunProgress {
+    /// Next checkpoint to collect
+    pub current_checkpoint: usize,
+    /// Checkpoint skipped in this run that the racer hasn't gone back for
+    pub missed: Option<usize>,
+}
+
+impl RunProgress {
+    /// Moves a run from checkpoint `first` to `last` along for a racer at `position`
+    ///
+    /// The reset checkpoint is checked first, then the next checkpoint, then whether the racer
+    /// skipped it. A skip is only reported once, and restarts the run when `policy` voids it.
+    pub fn step(&mut self, course: &Course, (first, last): (usize, usize), policy: MissedCheckpointPolicy, position: &Position) -> Vec<RunEvent> {
+        let mut events = Vec::new();
+        if course.is_in_reset(position) {
+            self.restart(first);
+            events.push(RunEvent::Reset);
+        }
+        if race_state(self.current_checkpoint, (first, last)) != RaceState::Finished && course.is_in_checkpoint(self.current_checkpoint, position) {
+            if self.missed == Some(self.current_checkpoint) {
+                self.missed = None;
+            }
+            self.current_checkpoint += 1;
+            events.push(RunEvent::Collect);
+        }
+        if race_state(self.current_checkpoint, (first, last)) == RaceState::Racing && self.missed != Some(self.current_checkpoint) {
+            if let Some(skipped) = course.skipped_checkpoint(self.current_checkpoint, last, position) {
+                self.missed = Some(skipped);
+                events.push(RunEvent::Skip(skipped));
+                if policy == MissedCheckpointPolicy::Void {
+                    self.restart(first);
+                }
+            }
+        }
+        events
+    }
+
+    /// Goes back to waiting for checkpoint `first`
+    pub fn restart(&mut self, first: usize) {
+        self.current_checkpoint = first;
+        self.missed = None;
+    }
+}
+
 pub struct RaceContext {
     pub selected_cup: Option<String>,
     pub selected_course: Option<Course>,
-    pub current_checkpoint: usize,
+    pub course_settings: CourseSettings,
+    pub progress: RunProgress,
     pub start_time: Instant,
     pub checkpoint_times: Vec<Duration>,
     pub race_state: RaceState,
+    pub reference_line: Option<Polyline>,
+    /// LiveSplit timer that follows the race, if one is set up
+    pub livesplit: Option<LiveSplitClient>,
+    /// Checkpoints being practiced from and to, instead of racing the whole course
+    pub practice: Option<(usize, usize)>,
 
     instants: (TimePosition, TimePosition),
+    heading: Option<f32>,
+    wrong_way: WrongWayDetector,
+    /// When the racer was caught skipping the checkpoint the run in progress is missing
+    missed_since: Option<Instant>,
+    /// Skip that voided the last run, kept only to warn about it for a while after restarting
+    voided: Option<(usize, Instant)>,
     distance_queue: VecDeque<f32>,
     gw2_data: GW2Data,
 }
@@ -61,17 +166,29 @@ impl RaceContext {
         RaceContext {
             selected_cup: None,
             selected_course: None,
-            current_checkpoint: 0usize,
+            course_settings: CourseSettings::default(),
+            progress: RunProgress::default(),
             start_time: Instant::now(),
             checkpoint_times: Vec::new(),
             race_state: RaceState::WaitingToStart,
+            reference_line: None,
+            livesplit: None,
+            practice: None,
             instants: (TimePosition::new(), TimePosition::new()),
+            heading: None,
+            wrong_way: WrongWayDetector::default(),
+            missed_since: None,
+            voided: None,
             distance_queue: VecDeque::from(vec![0f32, 0f32]),
             gw2_data: data,
         }
     }
 
     pub fn load_course(&mut self, track: &String) -> Result<()> {
+        self.reference_line = Trail::import(&format!("data/trails/{}.trl", track))?.map(|trail| Polyline::new(trail.points));
+        self.course_settings = CourseSettings::load(track)?;
+        self.progress.missed = None;
+        self.voided = None;
         std::fs::create_dir_all("data/courses")?;
         let filepath = format!("data/courses/{}.csv", track);
         if Path::new(&filepath).is_file() {
@@ -89,6 +206,18 @@ impl RaceContext {
         &self.gw2_data.racer.name
     }
 
+    pub fn map_id(&self) -> u32 {
+        self.gw2_data.map_id
+    }
+
+    pub fn continent_transform(&self) -> Option<geoexport::ContinentTransform> {
+        Some(geoexport::ContinentTransform::from_sample(&self.gw2_data.racer.position, self.gw2_data.continent?))
+    }
+
+    pub fn position(&self) -> guild_wars_handler::Position {
+        self.gw2_data.racer.position
+    }
+
     pub fn x(&self) -> f32 {
         self.gw2_data.racer.position[0]
     }
@@ -109,55 +238,77 @@ impl RaceContext {
     }
 
     pub fn restart_course(&mut self) {
-        self.current_checkpoint = 0;
+        self.progress.restart(self.checkpoint_range().0);
+        self.abandon_run();
+    }
+
+    /// Throws away the times of the run in progress, once its progress has been restarted
+    pub fn abandon_run(&mut self) {
+        if let (Some(livesplit), true) = (&mut self.livesplit, self.practice.is_none() && !self.checkpoint_times.is_empty()) {
+            livesplit.reset();
+        }
         self.clear_checkpoint_times();
     }
 
-    pub fn peek_current_checkpoint(&self) -> Checkpoint {
-        self.selected_course.as_ref().unwrap().checkpoints[self.current_checkpoint]
+    /// Switches between practicing part of the course and racing all of it, abandoning any run in progress
+    pub fn set_practice(&mut self, practice: Option<(usize, usize)>) {
+        self.restart_course();
+        self.practice = practice;
+        self.progress.restart(self.checkpoint_range().0);
     }
 
-    pub fn update_state(&mut self) {
-        self.race_state = match self.current_checkpoint {
-            0 => RaceState::WaitingToStart,
-            cp if cp < self.selected_course.as_ref().unwrap().checkpoints.len() => RaceState::Racing ,
-            _ => RaceState::Finished,
-        }
+    /// First and last checkpoint of the run, which is the whole course unless practicing
+    pub fn checkpoint_range(&self) -> (usize, usize) {
+        self.practice.unwrap_or((0, self.selected_course.as_ref().map_or(0, |c| c.checkpoints.len().saturating_sub(1))))
     }
 
-    pub fn collect_checkpoint(&mut self) {
-        if self.race_state == RaceState::Finished {
-            return;
-        }
-        if self.race_state == RaceState::WaitingToStart {
-            self.start_timer();
-        }
-        self.record_checkpoint_time();
-        self.current_checkpoint += 1;
+    pub fn update_state(&mut self) {
+        self.race_state = race_state(self.progress.current_checkpoint, self.checkpoint_range());
     }
 
-    pub fn is_in_current_checkpoint(&self) -> bool {
-        if self.current_checkpoint < self.selected_course.as_ref().unwrap().checkpoints.len() {
-            if self.current_cp_distance() < self.peek_current_checkpoint().radius as f32 {
-                return true
+    /// Follows the racer through the checkpoints of the selected course for one tick
+    ///
+    /// Only the run's progress is moved along. The caller goes through the events in order,
+    /// calling [`RaceContext::collect_checkpoint`] for every checkpoint collected and
+    /// [`RaceContext::abandon_run`] for every restart, so it can save the attempt first.
+    pub fn step(&mut self) -> Vec<RunEvent> {
+        let range = self.checkpoint_range();
+        let policy = self.course_settings.missed_checkpoint;
+        let Some(course) = &self.selected_course else {
+            return Vec::new()
+        };
+        let events = self.progress.step(course, range, policy, &self.gw2_data.racer.position);
+        for event in &events {
+            if let RunEvent::Skip(skipped) = event {
+                // a voided run keeps its warning up for a while, so the racer sees why it restarted
+                match policy {
+                    MissedCheckpointPolicy::Recover => self.missed_since = Some(Instant::now()),
+                    MissedCheckpointPolicy::Void => self.voided = Some((*skipped, Instant::now())),
+                }
             }
         }
-        
-        false
+        events
     }
 
-    pub fn is_in_reset_checkpoint(&self) -> bool {
-        if let (Some(dst), Some(cp)) = (self.reset_cp_distance(), self.selected_course.as_ref().unwrap().reset) {
-            if dst < cp.radius as f32 {
-                return true
+    /// Takes the time of the checkpoint the run just collected
+    pub fn collect_checkpoint(&mut self) {
+        let starting = self.checkpoint_times.is_empty();
+        if starting {
+            self.start_timer();
+        }
+        // the time is taken before anything else, so nothing else can end up in the splits
+        self.record_checkpoint_time();
+        if let (Some(livesplit), true) = (&mut self.livesplit, self.practice.is_none()) {
+            if starting {
+                livesplit.start_timer();
+            } else if let Some(time) = self.checkpoint_times.last() {
+                livesplit.split(*time);
             }
         }
-
-        false
     }
 
     pub fn current_cp_distance(&self) -> f32 {
-        let checkpoint = &self.selected_course.as_ref().unwrap().checkpoints[self.current_checkpoint];
+        let checkpoint = &self.selected_course.as_ref().unwrap().checkpoints[self.progress.current_checkpoint];
         euclidian_distance_3d(&self.gw2_data.racer.position, &checkpoint.point())
     }
 
@@ -169,6 +320,42 @@ impl RaceContext {
         None
     }
 
+    /// Direction the racer last moved in, in radians clockwise from north
+    pub fn heading(&self) -> Option<f32> {
+        self.heading
+    }
+
+    /// Where the next checkpoint is relative to the camera, if there is one left to collect
+    ///
+    /// The camera has no direction on the map when looking straight down, so the character's
+    /// facing is used instead.
+    pub fn next_checkpoint_bearing(&self) -> Option<Bearing> {
+        let target = self.selected_course.as_ref()?.checkpoints.get(self.progress.current_checkpoint)?.point();
+        let position = &self.gw2_data.racer.position;
+        compass::bearing(position, &self.gw2_data.camera.front, &target)
+            .or_else(|| compass::bearing(position, &self.gw2_data.racer.front, &target))
+    }
+
+    /// Checkpoint to warn the racer about having skipped, and how long ago they skipped it
+    ///
+    /// The warning lasts until the checkpoint is collected or the course restarted, or for a few
+    /// seconds after the run it ruined was voided.
+    pub fn missed_checkpoint(&self) -> Option<(usize, Duration)> {
+        let (missed, since) = self.progress.missed.zip(self.missed_since).or(self.voided.filter(|(_, since)| since.elapsed() < MISSED_WARNING_DURATION))?;
+        Some((missed, since.elapsed()))
+    }
+
+    /// Whether the racer has been moving away from the next checkpoint for a while during a run
+    pub fn is_wrong_way(&self) -> bool {
+        self.wrong_way.is_wrong_way(Instant::now())
+    }
+
+    /// Distance from the racer to the closest point of the reference line, if one is loaded
+    pub fn reference_line_distance(&self) -> Option<f32> {
+        let projection = self.reference_line.as_ref()?.project(&self.gw2_data.racer.position)?;
+        Some(projection.offset)
+    }
+
     pub fn update(&mut self) -> Result<()> {
         self.gw2_data.update()?;
         self.instants.0 = self.instants.1;
@@ -176,6 +363,20 @@ impl RaceContext {
             time: Instant::now(),
             position: self.gw2_data.racer.position,
         };
+        // Mumble doesn't change every poll, so the heading is kept until the racer moves again
+        let (from, to) = (self.instants.0.position, self.instants.1.position);
+        if from[0] != to[0] || from[2] != to[2] {
+            self.heading = Some((to[0] - from[0]).atan2(to[2] - from[2]));
+        }
+        let next = self.selected_course.as_ref().and_then(|course| course.checkpoints.get(self.progress.current_checkpoint)).map(|cp| cp.point());
+        match (next, self.race_state) {
+            (Some(next), RaceState::Racing) => {
+                // facing doesn't say where the beetle goes when drifting, so only movement counts
+                let movement = self.heading.filter(|_| self.filtered_speed() > 0).map(f32::to_degrees);
+                self.wrong_way.update(&self.gw2_data.racer.position, movement, &next, Instant::now());
+            },
+            _ => self.wrong_way.reset(),
+        }
         self.distance_queue.push_back(self.dist_per_poll());
         if self.distance_queue.len() > 5 {
             self.distance_queue.pop_front();
@@ -229,4 +430,63 @@ impl RaceContext {
     fn clear_checkpoint_times(&mut self) {
         self.checkpoint_times = Vec::new();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(policy: MissedCheckpointPolicy) -> RaceContext {
+        let mut ctx = RaceContext::new(GW2Data::offline());
+        ctx.selected_course = Some(Course::from_points("test-course", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [200.0, 0.0, 0.0], [300.0, 0.0, 0.0]], 15));
+        ctx.course_settings.missed_checkpoint = policy;
+        ctx
+    }
+
+    /// Moves the racer and runs the checkpoint logic of one tick of the race loop
+    fn drive_to(ctx: &mut RaceContext, position: guild_wars_handler::Position) -> Option<usize> {
+        ctx.gw2_data.racer.position = position;
+        let mut missed = None;
+        for event in ctx.step() {
+            match event {
+                RunEvent::Reset => ctx.abandon_run(),
+                RunEvent::Collect => ctx.collect_checkpoint(),
+                RunEvent::Skip(skipped) => {
+                    missed = Some(skipped);
+                    if ctx.course_settings.missed_checkpoint == MissedCheckpointPolicy::Void {
+                        ctx.abandon_run();
+                    }
+                },
+            }
+        }
+        ctx.update_state();
+        missed
+    }
+
+    #[test]
+    fn test_void_skipped_checkpoint_again() {
+        let mut ctx = context(MissedCheckpointPolicy::Void);
+        for _ in 0..2 {
+            assert_eq!(drive_to(&mut ctx, [0.0, 0.0, 0.0]), None);
+            assert_eq!(ctx.race_state, RaceState::Racing);
+            assert_eq!(drive_to(&mut ctx, [200.0, 0.0, 0.0]), Some(1));
+            assert_eq!(ctx.race_state, RaceState::WaitingToStart);
+            assert_eq!(ctx.missed_checkpoint().map(|(missed, _)| missed), Some(1));
+        }
+    }
+
+    #[test]
+    fn test_recover_skipped_checkpoint() {
+        let mut ctx = context(MissedCheckpointPolicy::Recover);
+        drive_to(&mut ctx, [0.0, 0.0, 0.0]);
+        assert_eq!(drive_to(&mut ctx, [200.0, 0.0, 0.0]), Some(1));
+        // staying in the later checkpoint doesn't count as skipping again
+        assert_eq!(drive_to(&mut ctx, [200.0, 0.0, 0.0]), None);
+        assert_eq!(ctx.missed_checkpoint().map(|(missed, _)| missed), Some(1));
+        drive_to(&mut ctx, [100.0, 0.0, 0.0]);
+        assert_eq!(ctx.missed_checkpoint(), None);
+        drive_to(&mut ctx, [200.0, 0.0, 0.0]);
+        drive_to(&mut ctx, [300.0, 0.0, 0.0]);
+        assert_eq!(ctx.race_state, RaceState::Finished);
+    }
+}
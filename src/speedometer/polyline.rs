@@ -0,0 +1,150 @@
+use super::guild_wars_handler::Position;
+use super::util::euclidian_distance_3d;
+
+/// Polyline is a path through a series of points with the distance along the path precomputed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<Position>,
+    cumulative: Vec<f32>,
+}
+
+/// Closest point on a Polyline to some position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projection {
+    /// Index of the segment the closest point lies on
+    pub segment: usize,
+    /// Closest point on the path
+    pub point: Position,
+    /// Distance travelled along the path to reach the closest point
+    pub distance_along: f32,
+    /// Distance between the position and the closest point
+    pub offset: f32,
+}
+
+impl Polyline {
+    pub fn new(points: Vec<Position>) -> Polyline {
+        let mut cumulative: Vec<f32> = Vec::with_capacity(points.len());
+        let mut total = 0f32;
+        for (idx, point) in points.iter().enumerate() {
+            if idx > 0 {
+                total += euclidian_distance_3d(&points[idx - 1], point);
+            }
+            cumulative.push(total);
+        }
+        Polyline { points, cumulative }
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.cumulative.last().unwrap_or(&0f32)
+    }
+
+    /// Distance along the path at which the point at `idx` lies
+    pub fn distance_at(&self, idx: usize) -> f32 {
+        self.cumulative[idx]
+    }
+
+    /// Point lying `distance` along the path, clamped to the ends of the path
+    pub fn point_at(&self, distance: f32) -> Option<Position> {
+        let first = self.points.first()?;
+        if distance <= 0f32 || self.points.len() == 1 {
+            return Some(*first)
+        }
+        let idx = self.cumulative.partition_point(|d| *d < distance);
+        if idx >= self.points.len() {
+            return self.points.last().copied()
+        }
+        let (a, b) = (self.points[idx - 1], self.points[idx]);
+        let length = self.cumulative[idx] - self.cumulative[idx - 1];
+        let t = if length > 0f32 { (distance - self.cumulative[idx - 1]) / length } else { 0f32 };
+        Some(lerp(&a, &b, t))
+    }
+
+    /// Finds the closest point on the path to `position`
+    pub fn project(&self, position: &Position) -> Option<Projection> {
+        self.project_within(position, 0, self.points.len())
+    }
+
+    /// Finds the closest point to `position` on the segments starting between `from` and `to`
+    ///
+    /// Restricting the search window keeps projections from jumping between parts of a path
+    /// that cross over or run alongside each other. An empty path has nothing to project onto.
+    pub fn project_within(&self, position: &Position, from: usize, to: usize) -> Option<Projection> {
+        if self.points.is_empty() {
+            return None
+        }
+        if self.points.len() == 1 {
+            return Some(Projection {
+                segment: 0,
+                point: self.points[0],
+                distance_along: 0f32,
+                offset: euclidian_distance_3d(position, &self.points[0]),
+            })
+        }
+        let to = usize::min(to, self.points.len() - 1);
+        let mut best: Option<Projection> = None;
+        for idx in from..to {
+            let (a, b) = (&self.points[idx], &self.points[idx + 1]);
+            let t = segment_parameter(a, b, position);
+            let point = lerp(a, b, t);
+            let offset = euclidian_distance_3d(position, &point);
+            if best.is_none_or(|p| offset < p.offset) {
+                best = Some(Projection {
+                    segment: idx,
+                    point,
+                    distance_along: self.cumulative[idx] + euclidian_distance_3d(a, &point),
+                    offset,
+                });
+            }
+        }
+        best
+    }
+}
+
+/// Position of the closest point to `p` on the segment a-b, from 0 at a to 1 at b
+fn segment_parameter(a: &Position, b: &Position, p: &Position) -> f32 {
+    let ab = [b[0]-a[0], b[1]-a[1], b[2]-a[2]];
+    let ap = [p[0]-a[0], p[1]-a[1], p[2]-a[2]];
+    let length_sq = ab[0]*ab[0] + ab[1]*ab[1] + ab[2]*ab[2];
+    if length_sq == 0f32 {
+        return 0f32
+    }
+    ((ap[0]*ab[0] + ap[1]*ab[1] + ap[2]*ab[2]) / length_sq).clamp(0f32, 1f32)
+}
+
+fn lerp(a: &Position, b: &Position, t: f32) -> Position {
+    [a[0] + (b[0]-a[0])*t, a[1] + (b[1]-a[1])*t, a[2] + (b[2]-a[2])*t]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_at() {
+        let line = Polyline::new(vec![[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [10.0, 0.0, 10.0]]);
+        assert_eq!(line.length(), 20.0);
+        assert_eq!(line.point_at(-1.0), Some([0.0, 0.0, 0.0]));
+        assert_eq!(line.point_at(5.0), Some([5.0, 0.0, 0.0]));
+        assert_eq!(line.point_at(15.0), Some([10.0, 0.0, 5.0]));
+        assert_eq!(line.point_at(25.0), Some([10.0, 0.0, 10.0]));
+    }
+
+    #[test]
+    fn test_project() {
+        let line = Polyline::new(vec![[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [10.0, 0.0, 10.0]]);
+        let projection = line.project(&[12.0, 0.0, 4.0]).unwrap();
+        assert_eq!(projection.segment, 1);
+        assert_eq!(projection.point, [10.0, 0.0, 4.0]);
+        assert_eq!(projection.distance_along, 14.0);
+        assert_eq!(projection.offset, 2.0);
+    }
+
+    #[test]
+    fn test_empty() {
+        let line = Polyline::new(Vec::new());
+        assert_eq!(line.length(), 0.0);
+        assert_eq!(line.point_at(5.0), None);
+        assert_eq!(line.project(&[1.0, 0.0, 1.0]), None);
+        assert_eq!(line.project_within(&[1.0, 0.0, 1.0], 0, 10), None);
+    }
+}
@@ -1,8 +1,9 @@
-use std::{fs::{File, create_dir_all}, io::Write, path::Path};
+use std::{fs::{File, create_dir_all}, io::{Read, Write}, path::Path};
 use super::util::{Importable, Exportable};
 
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, Context};
+use flate2::read::GzDecoder;
 use log;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -27,9 +28,39 @@ pub struct RaceLogEntry {
     pub map_angle: f32,
 }
 
+impl RaceLogEntry {
+    /// Entry at `position` going `speed`, with the angles and acceleration left at zero
+    #[cfg(test)]
+    pub fn sample(position: [f32; 3], speed: f32, timestamp: f64) -> RaceLogEntry {
+        RaceLogEntry {
+            x: position[0],
+            y: position[1],
+            z: position[2],
+            speed,
+            cam_angle: 0.0,
+            beetle_angle: 0.0,
+            timestamp,
+            acceleration: 0.0,
+            map_angle: 0.0,
+        }
+    }
+}
+
 impl Importable for Vec<RaceLogEntry> {
+    /// Reads a racelog, which may have been gzipped to `<path>.gz` since it was saved
     fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
         log::info!("Importing racelog from path: {}", path);
+        let gzipped = format!("{}.gz", path);
+        let path = match (Path::new(path).exists(), Path::new(&gzipped).exists()) {
+            (true, _) => path,
+            (false, true) => &gzipped,
+            (false, false) => return Ok(None),
+        };
+        if path.ends_with(".gz") {
+            let mut data = String::new();
+            GzDecoder::new(File::open(path).context("Failed to open racelog")?).read_to_string(&mut data).context("Failed to decompress racelog")?;
+            return Ok(Some(parse_racelog(&data)?))
+        }
         let mut reader = csv::Reader::from_path(path)?;
         let iter = reader.deserialize();
         let mut entries: Vec<RaceLogEntry> = Vec::new();
@@ -40,19 +71,34 @@ impl Importable for Vec<RaceLogEntry> {
     }
 }
 
+/// Reads a racelog that was downloaded rather than saved to disk
+pub fn parse_racelog(data: &str) -> Result<Vec<RaceLogEntry>> {
+    let mut reader = csv::Reader::from_reader(data.as_bytes());
+    let mut entries: Vec<RaceLogEntry> = Vec::new();
+    for record in reader.deserialize() {
+        entries.push(record.context("Failed to parse racelog")?);
+    }
+    Ok(entries)
+}
+
 impl Exportable for Vec<RaceLogEntry> {
     fn export(&self, path: String) -> Result<()> {
-        log::info!("Exporting racelog to path: {}", path);
-        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create racelog directory")?;
-        let mut writer = csv::Writer::from_writer(vec![]);
-        for entry in self.iter() {
-            writer.serialize(entry)?;
-        }
+        write_racelog(self, path)
+    }
+}
 
-        let mut file = File::create(path).context("Failed to create racelog file")?;
-        file.write_all(&writer.into_inner()?)?;
-        Ok(())
+/// Writes a race log as CSV, for logs that aren't held in a Vec
+pub fn write_racelog(log: &[RaceLogEntry], path: String) -> Result<()> {
+    log::info!("Exporting racelog to path: {}", path);
+    create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create racelog directory")?;
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for entry in log.iter() {
+        writer.serialize(entry)?;
     }
+
+    let mut file = File::create(path).context("Failed to create racelog file")?;
+    file.write_all(&writer.into_inner()?)?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -64,18 +110,7 @@ mod tests {
         use std::time::{SystemTime, UNIX_EPOCH};
         let time = SystemTime::now().duration_since(UNIX_EPOCH)?;
         let path = String::from(format!("/tmp/speedylemon_dev_log_{}.csv", time.as_millis()));
-        let entry = RaceLogEntry {
-            x: 0.0,
-            y: 1.0,
-            z: 2.0,
-            speed: 99.0,
-            cam_angle: 0.0,
-            beetle_angle: 0.0,
-            timestamp: 15.0,
-            acceleration: 0.0,
-            map_angle: 0.0,
-        };
-        let racelog = vec![entry];
+        let racelog = vec![RaceLogEntry::sample([0.0, 1.0, 2.0], 99.0, 15.0)];
         racelog.export(path.clone())?;
         let imported = Vec::import(&path)?;
         for (r, i) in racelog.iter().zip(imported.unwrap()) {
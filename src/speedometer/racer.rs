@@ -2,6 +2,8 @@ use super::guild_wars_handler::Position;
 
 pub struct Racer {
     pub position: Position,
+    /// Direction the character is facing
+    pub front: Position,
     pub name: String,
 }
 
@@ -9,6 +11,7 @@ impl Racer {
     pub fn new() -> Racer {
         Racer {
             position: [0f32; 3],
+            front: [0f32; 3],
             name: String::new(),
         }
     }
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use super::course::Course;
+use super::coursesettings::MissedCheckpointPolicy;
+use super::racelog::RaceLogEntry;
+use super::{race_state, RaceState, RunEvent, RunProgress};
+
+/// What replaying a race log through a course's checkpoints found
+#[derive(Clone, Debug, PartialEq)]
+pub struct Retime {
+    /// Time each checkpoint was collected, counted from the start, for the last attempt in the log
+    pub checkpoint_times: Vec<Duration>,
+    /// Number of times the log went through the reset checkpoint mid-run
+    pub resets: usize,
+    pub state: RaceState,
+}
+
+impl Retime {
+    /// Replays `log` the way RaceContext follows a live run
+    ///
+    /// Each sample goes through the same [`RunProgress::step`] the speedometer takes every tick,
+    /// so a run that skips a checkpoint is voided or not following `policy`. Times are counted
+    /// from the sample that collected the start, so they can differ from the live timer by up to
+    /// one log interval.
+    pub fn new(course: &Course, policy: MissedCheckpointPolicy, log: &[RaceLogEntry]) -> Retime {
+        let range = (0, course.checkpoints.len().saturating_sub(1));
+        let mut progress = RunProgress::default();
+        let mut checkpoint_times: Vec<Duration> = Vec::new();
+        let mut resets = 0usize;
+        let mut start = 0f64;
+        let mut state = race_state(progress.current_checkpoint, range);
+
+        for entry in log {
+            for event in progress.step(course, range, policy, &[entry.x, entry.y, entry.z]) {
+                match event {
+                    RunEvent::Reset => {
+                        if state == RaceState::Racing {
+                            resets += 1;
+                        }
+                        checkpoint_times.clear();
+                    },
+                    RunEvent::Collect => {
+                        if checkpoint_times.is_empty() {
+                            start = entry.timestamp;
+                        }
+                        checkpoint_times.push(Duration::from_secs_f64((entry.timestamp - start).max(0f64)));
+                    },
+                    RunEvent::Skip(_) if policy == MissedCheckpointPolicy::Void => checkpoint_times.clear(),
+                    RunEvent::Skip(_) => {},
+                }
+            }
+            state = race_state(progress.current_checkpoint, range);
+            if state == RaceState::Finished {
+                break
+            }
+        }
+
+        Retime { checkpoint_times, resets, state }
+    }
+
+    /// Whether the log collects every checkpoint in order
+    pub fn is_valid(&self) -> bool {
+        self.state == RaceState::Finished
+    }
+
+    pub fn laptime(&self) -> Option<Duration> {
+        match self.is_valid() {
+            true => self.checkpoint_times.last().copied(),
+            false => None,
+        }
+    }
+
+    /// Checkpoint times of a valid run
+    pub fn finished_times(&self) -> Option<&Vec<Duration>> {
+        self.is_valid().then_some(&self.checkpoint_times)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(points: &[(f32, f64)]) -> Vec<RaceLogEntry> {
+        points.iter().map(|(x, timestamp)| RaceLogEntry::sample([*x, 0.0, 0.0], 0.0, *timestamp)).collect()
+    }
+
+    fn course() -> Course {
+        let mut course = Course::from_points("test-course", &[[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [200.0, 0.0, 0.0]], 15);
+        course.add_reset(-100.0, 0.0, 0.0, 15);
+        course
+    }
+
+    #[test]
+    fn test_retime() {
+        let retime = Retime::new(&course(), MissedCheckpointPolicy::Recover, &log(&[(-20.0, 4.0), (0.0, 5.0), (50.0, 6.0), (100.0, 7.5), (150.0, 8.0), (200.0, 9.0)]));
+        assert!(retime.is_valid());
+        assert_eq!(retime.checkpoint_times, vec![Duration::ZERO, Duration::from_millis(2500), Duration::from_secs(4)]);
+        assert_eq!(retime.laptime(), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn test_reset_and_incomplete() {
+        let retime = Retime::new(&course(), MissedCheckpointPolicy::Recover, &log(&[(0.0, 0.0), (100.0, 1.0), (-100.0, 2.0), (0.0, 3.0), (100.0, 5.0)]));
+        assert_eq!(retime.resets, 1);
+        assert_eq!(retime.state, RaceState::Racing);
+        assert_eq!(retime.checkpoint_times, vec![Duration::ZERO, Duration::from_secs(2)]);
+        assert_eq!(retime.laptime(), None);
+        assert_eq!(retime.finished_times(), None);
+    }
+
+    #[test]
+    fn test_skipped_checkpoint() {
+        let skipping = log(&[(0.0, 0.0), (200.0, 1.0), (100.0, 2.0), (200.0, 3.0)]);
+        let recovered = Retime::new(&course(), MissedCheckpointPolicy::Recover, &skipping);
+        assert_eq!(recovered.laptime(), Some(Duration::from_secs(3)));
+        let voided = Retime::new(&course(), MissedCheckpointPolicy::Void, &skipping);
+        assert_eq!(voided.state, RaceState::WaitingToStart);
+        assert!(voided.checkpoint_times.is_empty());
+        assert_eq!(voided.finished_times(), None);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fs::create_dir_all, path::Path, time::Duration};
+use std::{collections::BTreeMap, fs::create_dir_all, path::Path, time::Duration};
 
 use anyhow::{Result, Context};
 use serde::{Serialize, Deserialize};
@@ -29,6 +29,21 @@ impl RaceLap {
     }
 }
 
+impl RaceLap {
+    /// Keeps the best of two laps over the same checkpoints: the faster PB with its splits, and
+    /// the faster of each best segment
+    pub fn merged(&self, other: &RaceLap) -> RaceLap {
+        let pb = if other.pb_laptime < self.pb_laptime { other } else { self };
+        RaceLap {
+            pb_laptime: pb.pb_laptime,
+            splits: Splits {
+                pb: pb.splits.pb.clone(),
+                best: self.splits.best.iter().zip(other.splits.best.iter()).map(|(a, b)| u64::min(*a, *b)).collect(),
+            },
+        }
+    }
+}
+
 impl Importable for RaceLap {
     fn import(path: &String) -> anyhow::Result<Option<Self>> where Self: Sized {
         log::info!("Importing checkpoint splits from {}", path);
@@ -91,6 +106,63 @@ pub fn update_track_data(checkpoint_times: &Vec<Duration>, path: String) -> Resu
     Ok(new_data)
 }
 
+/// Best times for practicing parts of a course, kept apart from the full-run PB
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PracticeLaps {
+    /// Laps keyed by the checkpoints practiced between, e.g. "3-7"
+    pub laps: BTreeMap<String, RaceLap>,
+}
+
+impl PracticeLaps {
+    pub fn path(track: &str) -> String {
+        format!("data/splits/{}.practice.toml", track)
+    }
+
+    pub fn load(track: &str) -> Result<PracticeLaps> {
+        Ok(PracticeLaps::import(&PracticeLaps::path(track))?.unwrap_or_default())
+    }
+
+    fn key(from: usize, to: usize) -> String {
+        format!("{}-{}", from, to)
+    }
+
+    pub fn get(&self, from: usize, to: usize) -> Option<&RaceLap> {
+        self.laps.get(&PracticeLaps::key(from, to))
+    }
+
+    /// Records a practice run from checkpoint `from` to `to`, returning the updated best times
+    pub fn update(&mut self, from: usize, to: usize, checkpoint_times: &Vec<Duration>) -> RaceLap {
+        let lap = match self.get(from, to) {
+            Some(previous) => calculate_pb(previous, checkpoint_times),
+            None => RaceLap::new(checkpoint_times),
+        };
+        self.laps.insert(PracticeLaps::key(from, to), lap.clone());
+        lap
+    }
+}
+
+impl Importable for PracticeLaps {
+    fn import(path: &String) -> anyhow::Result<Option<Self>> where Self: Sized {
+        log::info!("Importing practice splits from {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let toml_str = std::fs::read_to_string(path).context("Failed to read toml file")?;
+        let laps = toml::from_str(&toml_str).context("Failed to parse toml")?;
+        Ok(Some(laps))
+    }
+}
+
+impl Exportable for PracticeLaps {
+    fn export(&self, path: String) -> anyhow::Result<()> {
+        log::info!("Exporting practice splits to {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create splits directory")?;
+        let toml_str = toml::to_string(&self)?;
+        std::fs::write(path, toml_str)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -186,6 +258,35 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_merged() {
+        let local = RaceLap { pb_laptime: 300, splits: Splits { pb: vec![100, 200], best: vec![100, 150] } };
+        let imported = RaceLap { pb_laptime: 400, splits: Splits { pb: vec![150, 250], best: vec![90, 250] } };
+        let expected = RaceLap { pb_laptime: 300, splits: Splits { pb: vec![100, 200], best: vec![90, 150] } };
+        assert_eq!(local.merged(&imported), expected);
+        assert_eq!(imported.merged(&local), expected);
+    }
+
+    #[test]
+    fn test_practice_laps() -> Result<()> {
+        let mut laps = PracticeLaps::default();
+        laps.update(2, 4, &vec![Duration::from_millis(0), Duration::from_millis(300), Duration::from_millis(500)]);
+        let lap = laps.update(2, 4, &vec![Duration::from_millis(0), Duration::from_millis(200), Duration::from_millis(600)]);
+        assert_eq!(lap, RaceLap {
+            pb_laptime: 500,
+            splits: Splits {
+                pb: vec![300, 200],
+                best: vec![200, 200],
+            }
+        });
+        assert_eq!(laps.get(1, 4), None);
+
+        let path = String::from("/tmp/speedylemon-test-practice.toml");
+        laps.export(path.clone())?;
+        assert_eq!(PracticeLaps::import(&path)?, Some(laps));
+        Ok(())
+    }
+
     #[test]
     fn test_export_import() -> Result<()> {
         let path = String::from("/tmp/speedylemon-test-splits.toml");
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use super::history::{RunFilter, RunHistory, RunStatus};
+
+/// How attempts at a course went, and where they tend to be thrown away
+#[derive(Clone, Debug, PartialEq)]
+pub struct CourseStatistics {
+    pub attempts: usize,
+    pub finished: usize,
+    pub resets: usize,
+    /// Number of resets by the last checkpoint collected before resetting
+    pub resets_by_checkpoint: Vec<usize>,
+    /// Time spent on reset attempts in each section, counted from the checkpoint at the start of
+    /// the section until the reset
+    pub time_wasted: Vec<Duration>,
+    /// Total time spent on attempts that were reset
+    pub total_wasted: Duration,
+}
+
+impl CourseStatistics {
+    /// Statistics for the attempts in `history` driven on the course layout with `fingerprint`
+    pub fn new(history: &RunHistory, fingerprint: &str, checkpoints: usize) -> CourseStatistics {
+        let runs = history.query(&RunFilter { fingerprint: Some(fingerprint.to_string()), ..Default::default() });
+        let mut resets_by_checkpoint = vec![0usize; checkpoints];
+        let mut time_wasted = vec![Duration::ZERO; checkpoints];
+        let mut total_wasted = Duration::ZERO;
+
+        for run in runs.iter().filter(|run| run.status == RunStatus::Reset) {
+            let Some(reached) = run.reached_checkpoint().filter(|reached| *reached < checkpoints) else {
+                continue
+            };
+            resets_by_checkpoint[reached] += 1;
+            // older runs didn't record when they were reset, so only the time up to the last checkpoint is known
+            let elapsed = Duration::from_millis(run.elapsed.unwrap_or(run.checkpoint_times[reached]));
+            time_wasted[reached] += elapsed.saturating_sub(Duration::from_millis(run.checkpoint_times[reached]));
+            total_wasted += elapsed;
+        }
+
+        CourseStatistics {
+            attempts: runs.len(),
+            finished: runs.iter().filter(|run| run.status == RunStatus::Finished).count(),
+            resets: resets_by_checkpoint.iter().sum(),
+            resets_by_checkpoint,
+            time_wasted,
+            total_wasted,
+        }
+    }
+
+    /// Share of attempts that finished, from 0 to 1
+    pub fn completion_rate(&self) -> f32 {
+        if self.attempts == 0 {
+            return 0f32
+        }
+        self.finished as f32 / self.attempts as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speedometer::course::Course;
+    use crate::speedometer::history::RunRecord;
+
+    fn run(course: &Course, times: &[u64], status: RunStatus, elapsed: Option<u64>) -> RunRecord {
+        let times: Vec<Duration> = times.iter().map(|t| Duration::from_millis(*t)).collect();
+        let record = RunRecord::new(course, "Racer", &times, status, None);
+        match elapsed {
+            Some(elapsed) => record.with_elapsed(Duration::from_millis(elapsed)),
+            None => record,
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let course = Course::from_points("test-course", &[[0.0, 0.0, 0.0], [50.0, 0.0, 0.0], [100.0, 0.0, 0.0]], 15);
+        let history = RunHistory {
+            runs: vec![
+                run(&course, &[0, 1000, 2000], RunStatus::Finished, Some(2000)),
+                run(&course, &[0, 1000], RunStatus::Reset, Some(1500)),
+                run(&course, &[0, 1200], RunStatus::Reset, None),
+                run(&course, &[0], RunStatus::Reset, Some(300)),
+                run(&course, &[0], RunStatus::Invalid, Some(100)),
+            ],
+        };
+        let stats = CourseStatistics::new(&history, &course.fingerprint(), course.checkpoints.len());
+        assert_eq!(stats.attempts, 5);
+        assert_eq!(stats.finished, 1);
+        assert_eq!(stats.resets, 3);
+        assert_eq!(stats.completion_rate(), 0.2);
+        assert_eq!(stats.resets_by_checkpoint, vec![1, 2, 0]);
+        assert_eq!(stats.time_wasted, vec![Duration::from_millis(300), Duration::from_millis(500), Duration::ZERO]);
+        assert_eq!(stats.total_wasted, Duration::from_millis(3000));
+
+        assert_eq!(CourseStatistics::new(&history, "other layout", 3).attempts, 0);
+    }
+}
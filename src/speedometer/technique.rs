@@ -0,0 +1,220 @@
+use std::fmt::Display;
+
+use serde::{Serialize, Deserialize};
+
+use super::racelog::RaceLogEntry;
+
+/// Fastest a beetle goes while boosting in a straight line, see RaceContext::filtered_speed
+const BOOST_MAX_SPEED: f32 = 102f32;
+/// Top speed without boosting
+const BOOST_MIN_SPEED: f32 = 75f32;
+
+/// Speed lost within BUMP_WINDOW that counts as running into something
+const BUMP_SPEED_LOSS: f32 = 25f32;
+const BUMP_WINDOW: f64 = 0.25;
+
+/// Height changed per unit driven past which the beetle isn't on the ground anymore, since no
+/// drivable slope is steeper than 45 degrees
+const AIRBORNE_SLOPE: f32 = 1f32;
+/// Height change per second below which slopes are ignored, so standing still isn't airborne
+const AIRBORNE_MIN_RATE: f32 = 2f32;
+
+/// What the beetle is doing at a point of a run
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Technique {
+    /// Going faster than a boost can, which only drifting does
+    Drifting,
+    Boosting,
+    Coasting,
+    /// Jumping or falling
+    Airborne,
+    /// Losing a lot of speed at once, usually by hitting a wall or an obstacle
+    Bumped,
+}
+
+impl Display for Technique {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match *self {
+            Self::Drifting => "Drift",
+            Self::Boosting => "Boost",
+            Self::Coasting => "Coast",
+            Self::Airborne => "Air",
+            Self::Bumped => "Bump",
+        })
+    }
+}
+
+impl Technique {
+    pub const ALL: [Technique; 5] = [Technique::Drifting, Technique::Boosting, Technique::Coasting, Technique::Airborne, Technique::Bumped];
+}
+
+/// An unbroken stretch of a run spent on one technique
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct TechniquePhase {
+    pub technique: Technique,
+    /// Seconds since the start, as in the race log
+    pub start: f64,
+    pub end: f64,
+}
+
+/// How often, and for how long, a technique was used
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TechniqueCount {
+    pub count: usize,
+    /// Milliseconds spent on the technique
+    pub duration: u64,
+}
+
+/// Counts and durations of every technique over part of a run
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TechniqueSummary {
+    pub drifting: TechniqueCount,
+    pub boosting: TechniqueCount,
+    pub coasting: TechniqueCount,
+    pub airborne: TechniqueCount,
+    pub bumped: TechniqueCount,
+}
+
+impl TechniqueSummary {
+    /// Sums up the phases overlapping the time from `start` to `end`, cutting off the parts outside it
+    pub fn new(phases: &[TechniquePhase], start: f64, end: f64) -> TechniqueSummary {
+        let mut summary = TechniqueSummary::default();
+        for phase in phases.iter().filter(|phase| phase.end > start && phase.start < end) {
+            let count = summary.get_mut(phase.technique);
+            count.count += 1;
+            count.duration += ((phase.end.min(end) - phase.start.max(start)).max(0f64) * 1000f64).round() as u64;
+        }
+        summary
+    }
+
+    pub fn get(&self, technique: Technique) -> TechniqueCount {
+        match technique {
+            Technique::Drifting => self.drifting,
+            Technique::Boosting => self.boosting,
+            Technique::Coasting => self.coasting,
+            Technique::Airborne => self.airborne,
+            Technique::Bumped => self.bumped,
+        }
+    }
+
+    fn get_mut(&mut self, technique: Technique) -> &mut TechniqueCount {
+        match technique {
+            Technique::Drifting => &mut self.drifting,
+            Technique::Boosting => &mut self.boosting,
+            Technique::Coasting => &mut self.coasting,
+            Technique::Airborne => &mut self.airborne,
+            Technique::Bumped => &mut self.bumped,
+        }
+    }
+}
+
+/// Lists the techniques used, e.g. "Drift 2x 1.5s, Boost 1x 0.8s", or "-" if none were
+impl Display for TechniqueSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let used: Vec<String> = Technique::ALL.iter()
+            .map(|technique| (technique, self.get(*technique)))
+            .filter(|(_, count)| count.count > 0)
+            .map(|(technique, count)| format!("{} {}x {:.1}s", technique, count.count, count.duration as f64 / 1000f64))
+            .collect();
+        match used.is_empty() {
+            true => f.pad("-"),
+            false => f.pad(&used.join(", ")),
+        }
+    }
+}
+
+/// Splits a race log into phases of the technique used at each sample
+///
+/// Bumps take priority, then being airborne, since speed doesn't say much in the air. Otherwise
+/// the speed tells drifting, boosting and coasting apart.
+pub fn phases(log: &[RaceLogEntry]) -> Vec<TechniquePhase> {
+    let mut phases: Vec<TechniquePhase> = Vec::new();
+    for (idx, entry) in log.iter().enumerate() {
+        let technique = classify(log, idx);
+        match phases.last_mut() {
+            Some(phase) if phase.technique == technique => phase.end = entry.timestamp,
+            last => {
+                // a phase lasts until the next one starts, so the durations add up to the whole run
+                if let Some(phase) = last {
+                    phase.end = entry.timestamp;
+                }
+                phases.push(TechniquePhase { technique, start: entry.timestamp, end: entry.timestamp });
+            },
+        }
+    }
+    phases
+}
+
+fn classify(log: &[RaceLogEntry], idx: usize) -> Technique {
+    let entry = &log[idx];
+    let recent_top = log[..idx].iter().rev()
+        .take_while(|previous| entry.timestamp - previous.timestamp <= BUMP_WINDOW)
+        .map(|previous| previous.speed)
+        .fold(entry.speed, f32::max);
+    if recent_top - entry.speed >= BUMP_SPEED_LOSS {
+        return Technique::Bumped
+    }
+    if let Some(previous) = idx.checked_sub(1).map(|previous| &log[previous]) {
+        let climb = (entry.y - previous.y).abs();
+        let run = ((entry.x - previous.x).powi(2) + (entry.z - previous.z).powi(2)).sqrt();
+        let time = (entry.timestamp - previous.timestamp) as f32;
+        if time > 0f32 && climb / time > AIRBORNE_MIN_RATE && climb > run * AIRBORNE_SLOPE {
+            return Technique::Airborne
+        }
+    }
+    match entry.speed {
+        speed if speed > BOOST_MAX_SPEED => Technique::Drifting,
+        speed if speed > BOOST_MIN_SPEED => Technique::Boosting,
+        _ => Technique::Coasting,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: f64, speed: f32, x: f32, y: f32) -> RaceLogEntry {
+        RaceLogEntry::sample([x, y, 0.0], speed, timestamp)
+    }
+
+    #[test]
+    fn test_phases() {
+        let log = vec![
+            entry(0.0, 50.0, 0.0, 0.0),
+            entry(0.1, 90.0, 5.0, 0.0),
+            entry(0.2, 120.0, 10.0, 0.0),
+            entry(0.3, 125.0, 15.0, 0.0),
+            // straight into a wall
+            entry(0.4, 60.0, 16.0, 0.0),
+            entry(0.8, 60.0, 20.0, 0.0),
+            // off a ledge
+            entry(0.9, 60.0, 21.0, -3.0),
+            entry(1.0, 60.0, 22.0, -3.0),
+        ];
+        let techniques: Vec<(Technique, f64, f64)> = phases(&log).iter().map(|p| (p.technique, p.start, p.end)).collect();
+        assert_eq!(techniques, vec![
+            (Technique::Coasting, 0.0, 0.1),
+            (Technique::Boosting, 0.1, 0.2),
+            (Technique::Drifting, 0.2, 0.4),
+            (Technique::Bumped, 0.4, 0.8),
+            (Technique::Coasting, 0.8, 0.9),
+            (Technique::Airborne, 0.9, 1.0),
+            (Technique::Coasting, 1.0, 1.0),
+        ]);
+    }
+
+    #[test]
+    fn test_summary() {
+        let phases = vec![
+            TechniquePhase { technique: Technique::Drifting, start: 0.0, end: 1.0 },
+            TechniquePhase { technique: Technique::Coasting, start: 1.0, end: 1.5 },
+            TechniquePhase { technique: Technique::Drifting, start: 1.5, end: 3.0 },
+        ];
+        let summary = TechniqueSummary::new(&phases, 0.5, 2.0);
+        assert_eq!(summary.drifting, TechniqueCount { count: 2, duration: 1000 });
+        assert_eq!(summary.get(Technique::Coasting), TechniqueCount { count: 1, duration: 500 });
+        assert_eq!(summary.bumped, TechniqueCount::default());
+        assert_eq!(summary.to_string(), "Drift 2x 1.0s, Coast 1x 0.5s");
+        assert_eq!(TechniqueSummary::default().to_string(), "-");
+    }
+}
@@ -0,0 +1,213 @@
+use std::{fs::{File, create_dir_all}, io::Write, path::Path};
+
+use anyhow::{bail, Result, Context};
+use log;
+
+use super::course::Course;
+use super::guild_wars_handler::Position;
+use super::polyline::Polyline;
+use super::racelog::RaceLogEntry;
+use super::util::{turn_angle, Exportable, Importable};
+
+/// Version number written at the start of every TacO trail file
+const TRAIL_VERSION: u32 = 0;
+
+/// Trail is a TacO/Blish HUD `.trl` trail: a map id followed by a stream of points
+///
+/// ## File format
+/// All values are little-endian
+/// - `u32` version (always 0)
+/// - `u32` map id
+/// - `f32` x, y, z for every point until the end of the file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trail {
+    pub map_id: u32,
+    pub points: Vec<Position>,
+}
+
+impl Trail {
+    /// Builds a trail following the racing line recorded in a race log
+    pub fn from_log(map_id: u32, log: &[RaceLogEntry]) -> Trail {
+        Trail {
+            map_id,
+            points: log.iter().map(|entry| [entry.x, entry.y, entry.z]).collect(),
+        }
+    }
+
+    /// Reads a trail file, which needs at least two points to make a path
+    pub fn from_bytes(bytes: &[u8]) -> Result<Trail> {
+        if bytes.len() < 8 || !(bytes.len() - 8).is_multiple_of(12) {
+            bail!("Trail data has an invalid length of {} bytes", bytes.len());
+        }
+        if bytes.len() < 8 + 2 * 12 {
+            bail!("Trail has {} points, at least 2 are needed", (bytes.len() - 8) / 12);
+        }
+        let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset+4].try_into().unwrap());
+        let read_f32 = |offset: usize| f32::from_le_bytes(bytes[offset..offset+4].try_into().unwrap());
+        let version = read_u32(0);
+        if version != TRAIL_VERSION {
+            bail!("Unsupported trail version {}", version);
+        }
+        let points = (8..bytes.len()).step_by(12)
+            .map(|offset| [read_f32(offset), read_f32(offset + 4), read_f32(offset + 8)])
+            .collect();
+        Ok(Trail {
+            map_id: read_u32(4),
+            points,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(8 + self.points.len() * 12);
+        bytes.extend_from_slice(&TRAIL_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.map_id.to_le_bytes());
+        for point in self.points.iter() {
+            for coord in point {
+                bytes.extend_from_slice(&coord.to_le_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+impl Importable for Trail {
+    fn import(path: &String) -> Result<Option<Self>> where Self: Sized {
+        log::info!("Importing trail from path: {}", path);
+        if !Path::new(path).exists() {
+            return Ok(None)
+        }
+        let bytes = std::fs::read(path).context("Failed to read trail file")?;
+        Ok(Some(Trail::from_bytes(&bytes)?))
+    }
+}
+
+impl Exportable for Trail {
+    fn export(&self, path: String) -> Result<()> {
+        log::info!("Exporting trail to path: {}", path);
+        create_dir_all(Path::new(&path).parent().unwrap()).context("Failed to create trail directory")?;
+        let mut file = File::create(path).context("Failed to create trail file")?;
+        file.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+}
+
+/// How checkpoints are placed along a trail when it is turned into a Course
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheckpointPlacement {
+    /// A checkpoint every `n` units along the trail
+    Spacing(f32),
+    /// A checkpoint at every corner sharper than `degrees`, no closer together than `min_spacing`
+    Curvature { degrees: f32, min_spacing: f32 },
+}
+
+/// Distance looked ahead and behind a point when measuring how sharply the trail turns there,
+/// so that jitter between closely recorded points isn't mistaken for a corner
+const CURVATURE_WINDOW: f32 = 10f32;
+
+impl Trail {
+    /// Generates a course following the trail, from a start on its first point to an end on its last
+    pub fn to_course(&self, name: &str, placement: CheckpointPlacement, radius: i32) -> Course {
+        let line = Polyline::new(self.points.clone());
+        let mut points: Vec<Position> = Vec::new();
+        if let Some(first) = self.points.first() {
+            points.push(*first);
+        }
+        match placement {
+            CheckpointPlacement::Spacing(spacing) => {
+                let mut distance = spacing;
+                while spacing > 0f32 && distance < line.length() - spacing / 2f32 {
+                    points.push(line.point_at(distance).unwrap());
+                    distance += spacing;
+                }
+            },
+            CheckpointPlacement::Curvature { degrees, min_spacing } => {
+                points.append(&mut curvature_peaks(&line, degrees, min_spacing));
+            },
+        }
+        if self.points.len() > 1 {
+            points.push(*self.points.last().unwrap());
+        }
+        Course::from_points(name, &points, radius)
+    }
+}
+
+/// Finds the sharpest corners on the line, keeping them apart from each other and the line's ends
+fn curvature_peaks(line: &Polyline, degrees: f32, min_spacing: f32) -> Vec<Position> {
+    let length = line.length();
+    let mut candidates: Vec<(usize, f32)> = Vec::new();
+    for idx in 1..line.points.len().saturating_sub(1) {
+        let distance = line.distance_at(idx);
+        if distance < min_spacing || length - distance < min_spacing {
+            continue
+        }
+        let before = line.point_at(distance - CURVATURE_WINDOW).unwrap();
+        let after = line.point_at(distance + CURVATURE_WINDOW).unwrap();
+        let angle = turn_angle(&before, &line.points[idx], &after);
+        if angle >= degrees {
+            candidates.push((idx, angle));
+        }
+    }
+    candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    let mut peaks: Vec<usize> = Vec::new();
+    for (idx, _) in candidates {
+        let distance = line.distance_at(idx);
+        if peaks.iter().all(|peak| (line.distance_at(*peak) - distance).abs() >= min_spacing) {
+            peaks.push(idx);
+        }
+    }
+    peaks.sort();
+    peaks.into_iter().map(|idx| line.points[idx]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speedometer::checkpoint::Stepname;
+
+    #[test]
+    fn test_to_bytes() {
+        let trail = Trail {
+            map_id: 1206,
+            points: vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]],
+        };
+        let bytes = trail.to_bytes();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(&bytes[0..4], &0u32.to_le_bytes());
+        assert_eq!(&bytes[4..8], &1206u32.to_le_bytes());
+        assert_eq!(&bytes[8..12], &1.0f32.to_le_bytes());
+        assert_eq!(&bytes[16..20], &3.0f32.to_le_bytes());
+        assert_eq!(Trail::from_bytes(&bytes).unwrap(), trail);
+        assert!(Trail::from_bytes(&bytes[..31]).is_err());
+        // a single point or just the header isn't a path
+        assert!(Trail::from_bytes(&bytes[..20]).is_err());
+        assert!(Trail::from_bytes(&bytes[..8]).is_err());
+    }
+
+    #[test]
+    fn test_to_course_spacing() {
+        let trail = Trail {
+            map_id: 0,
+            points: vec![[0.0, 0.0, 0.0], [120.0, 0.0, 0.0]],
+        };
+        let course = trail.to_course("spaced", CheckpointPlacement::Spacing(30.0), 15);
+        let xs: Vec<f32> = course.checkpoints.iter().map(|cp| cp.x).collect();
+        assert_eq!(xs, vec![0.0, 30.0, 60.0, 90.0, 120.0]);
+        assert_eq!(course.checkpoints[0].stepname, Stepname::Start);
+        assert_eq!(course.checkpoints[4].stepname, Stepname::End);
+        assert_eq!(course.checkpoints[4].step, 4);
+    }
+
+    #[test]
+    fn test_to_course_curvature() {
+        let trail = Trail {
+            map_id: 0,
+            points: (0..=100).map(|x| [x as f32, 0.0, 0.0])
+                .chain((1..=100).map(|z| [100.0, 0.0, z as f32]))
+                .collect(),
+        };
+        let course = trail.to_course("corner", CheckpointPlacement::Curvature { degrees: 45.0, min_spacing: 20.0 }, 15);
+        assert_eq!(course.checkpoints.len(), 3);
+        assert_eq!(course.checkpoints[1].point(), [100.0, 0.0, 0.0]);
+    }
+}
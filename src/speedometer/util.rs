@@ -18,6 +18,27 @@ pub fn euclidian_distance_2d(a: &[f32; 3], b: &[f32; 3]) -> f32 {
     ((a[0]-b[0]).powi(2) + (a[2]-b[2]).powi(2)).sqrt()
 }
 
+/// Angle in degrees that the path a -> b -> c turns at b, ignoring height
+///
+/// 0 means the path carries straight on through b, 180 means it doubles back on itself.
+pub fn turn_angle(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> f32 {
+    let incoming = (b[2]-a[2]).atan2(b[0]-a[0]);
+    let outgoing = (c[2]-b[2]).atan2(c[0]-b[0]);
+    let mut angle = (outgoing - incoming).to_degrees().abs();
+    if angle > 180f32 {
+        angle = 360f32 - angle;
+    }
+    angle
+}
+
+/// Escapes `text` to go in XML text or a double quoted attribute
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 pub trait Timestamp {
     fn timestamp(&self) -> String;
 }
@@ -1,10 +1,10 @@
 use anyhow::{Result, Context};
 use beetlerank::BeetleRank;
 use itertools::Itertools;
-use crate::{speedometer::{checkpoint::Stepname, util::{Importable, Timestamp}}, track_selector::TrackSelectorState};
-use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crate::{minimap::Minimap, practice::PracticeSelector, speedometer::util::{Importable, Timestamp}, track_creator::{CourseEditor, CreatorMode}, track_selector::TrackSelectorState};
+use crossterm::{event::{self, Event, KeyCode, KeyEventKind}, style::Stylize};
 use feotui::{Border, Padding, Render, StatefulScrollingList};
-use crate::speedometer::{splits::*, checkpoint::Checkpoint, course::Course, guild_wars_handler::{self}, racelog::RaceLogEntry, splits::update_track_data, util::Exportable, RaceContext, RaceState};
+use crate::speedometer::{splits::*, analysis::RaceAnalysis, comparison::{live_projection, possible_time_save, projected_final, segment_delta, Comparison}, course::Course, coursesettings::MissedCheckpointPolicy, geoexport::{MapTransforms, TransformSampler}, ghost::{Ghost, GhostDelta}, history::{RunFilter, RunHistory, RunRecord, RunStatus}, livesplit::{LiveSplitClient, LiveSplitSettings}, logstore::{LogStore, RetentionPolicy}, statistics::CourseStatistics, technique::{Technique, TechniqueSummary}, geometry::{CourseStats, DEFAULT_AVERAGE_SPEED}, guild_wars_handler::{self, Position}, markerpack::MarkerPack, racelog::{parse_racelog, RaceLogEntry}, retime::Retime, trail::Trail, splits::update_track_data, util::Exportable, RaceContext, RaceState, RunEvent};
 use std::{fmt::Display, fs, path::Path, time::{Duration, Instant}};
 use feotui::Popup;
 use crate::DEBUG;
@@ -16,6 +16,9 @@ pub enum ProgramState {
     TrackSelector,
     TrackCreator,
     Speedometer,
+    History,
+    Statistics,
+    Practice,
 }
 
 impl Display for ProgramState {
@@ -24,6 +27,9 @@ impl Display for ProgramState {
             Self::TrackSelector => "Track Selector",
             Self::TrackCreator => "Track Creator",
             Self::Speedometer => "Speedometer",
+            Self::History => "History",
+            Self::Statistics => "Statistics",
+            Self::Practice => "Practice",
             Self::Quit => "Quit",
         })
     }
@@ -41,7 +47,7 @@ pub fn run() -> Result<()> {
     let mut last_log = Instant::now();
     let mut race_log: Vec<RaceLogEntry> = Vec::new();
     let mut old_racestate: RaceState;
-    let mut creating_course: Course = Course::new();
+    let mut editor = CourseEditor::new();
     let mut upload_response: Vec<String> = Vec::new();
 
     let mut trackselstate = TrackSelectorState::SelectCup;
@@ -50,11 +56,30 @@ pub fn run() -> Result<()> {
     let mut beetlestatelist = StatefulScrollingList::with_items(beetlerank.get_cups()?.clone()).with_scroll_style(feotui::ScrollStyle::Paging).with_viewport_length(10);
     beetlestatelist.select(0);
     let mut cup_window: Vec<String>;
+    let mut preview: Option<(String, Option<CourseStats>)> = None;
     let mut pb: Option<RaceLap> = None;
+    let mut history = RunHistory::default();
+    let livesplit = LiveSplitSettings::load()?;
+    if livesplit.enabled {
+        ctx.livesplit = Some(LiveSplitClient::new(livesplit));
+    }
+    let mut comparison = Comparison::PersonalBest;
+    let mut top_run: Option<(String, Option<Vec<u64>>)> = None;
+    let mut practice_selector = PracticeSelector::new(0);
+    let mut ghost: Option<Ghost> = None;
+    let mut analysis: Option<RaceAnalysis> = None;
+    let mut transforms = TransformSampler::default();
+    let mut minimap = Minimap::new();
+    let mut delta_trace: Vec<Option<f32>> = Vec::new();
 
     while state != ProgramState::Quit {
         ctx.update().context(format!("Failed to update SpeedyLemon Context Object"))?;
 
+        // continent transforms are saved once per map and session, so logs can be exported later
+        if let Some(transform) = ctx.continent_transform().and_then(|transform| transforms.sample(ctx.map_id(), transform, Instant::now())) {
+            MapTransforms::record(ctx.map_id(), transform).context("Failed to save map transform")?;
+        }
+
         if last_log.elapsed() >= log_delta && ctx.race_state == RaceState::Racing {
             last_log = Instant::now();
             race_log.push(RaceLogEntry {
@@ -70,16 +95,41 @@ pub fn run() -> Result<()> {
             });
         }
 
+        if state == ProgramState::TrackCreator {
+            editor.record(ctx.position());
+        }
+
+        let ghost_delta = match (&mut ghost, ctx.race_state) {
+            (Some(ghost), RaceState::WaitingToStart) => {
+                ghost.reset();
+                None
+            },
+            (Some(ghost), RaceState::Racing) if ctx.practice.is_none() => ghost.delta(&ctx.position(), ctx.start_time.elapsed()),
+            _ => None,
+        };
+        // one delta per race log sample, so the trace starts over whenever the log does
+        delta_trace.truncate(race_log.len());
+        if delta_trace.len() < race_log.len() {
+            delta_trace.push(ghost_delta.map(|delta| delta.time as f32));
+        }
+
         if let Some(_) = &ctx.selected_course {
-            // restart course if needed
-            if ctx.is_in_reset_checkpoint() {
-                ctx.restart_course();
-                race_log = Vec::new();
-            }
-            
-            // collect checkpoint if needed
-            if ctx.is_in_current_checkpoint() {
-                ctx.collect_checkpoint();
+            for event in ctx.step() {
+                match event {
+                    RunEvent::Reset => {
+                        record_attempt(&ctx, &race_log, &mut history, RunStatus::Reset, None)?;
+                        ctx.abandon_run();
+                        race_log = Vec::new();
+                    },
+                    RunEvent::Collect => ctx.collect_checkpoint(),
+                    // the course doesn't allow going back for a skipped checkpoint
+                    RunEvent::Skip(_) if ctx.course_settings.missed_checkpoint == MissedCheckpointPolicy::Void => {
+                        record_attempt(&ctx, &race_log, &mut history, RunStatus::Invalid, None)?;
+                        ctx.abandon_run();
+                        race_log = Vec::new();
+                    },
+                    RunEvent::Skip(_) => {},
+                }
             }
     
             old_racestate = ctx.race_state;
@@ -89,25 +139,45 @@ pub fn run() -> Result<()> {
             if ctx.race_state != old_racestate {
                 match ctx.race_state {
                     RaceState::Finished => {
-                        race_log.push(RaceLogEntry {
-                            x: ctx.x(),
-                            y: ctx.y(),
-                            z: ctx.z(),
-                            speed: ctx.filtered_speed() as f32,
-                            cam_angle: 0.0,
-                            beetle_angle: 0.0,
-                            timestamp: ctx.start_time.elapsed().as_millis() as f64 / 1000f64,
-                            acceleration: 0.0,
-                            map_angle: 0.0,
-                        });
-                        let track = &ctx.selected_course.clone().unwrap().name;
-                        let latest_laptime = ctx.checkpoint_times.last().unwrap().as_millis() as u64;
-                        let logfilepath = format!("./data/logs/{}_{}.csv", track, latest_laptime);
-                        race_log.export(String::from(&logfilepath)).context("Failed to export race log")?;
-                        let racelap = update_track_data(&ctx.checkpoint_times, String::from(format!("./data/splits/{}.toml", track))).context("Failed to export splits")?;
-                        pb = Some(racelap.clone());
-                        if *ctx.selected_cup.as_ref().unwrap() != "CUSTOM TRACKS".to_string() {
-                            upload_response = beetlerank.post_log(ctx.racer_name().clone(), track.clone(), logfilepath)?;
+                        if let Some((from, to)) = ctx.practice {
+                            let track = &ctx.selected_course.as_ref().unwrap().name;
+                            let mut laps = PracticeLaps::load(track)?;
+                            pb = Some(laps.update(from, to, &ctx.checkpoint_times));
+                            laps.export(PracticeLaps::path(track)).context("Failed to export practice splits")?;
+                            analysis = None;
+                        } else {
+                            race_log.push(RaceLogEntry {
+                                x: ctx.x(),
+                                y: ctx.y(),
+                                z: ctx.z(),
+                                speed: ctx.filtered_speed() as f32,
+                                cam_angle: 0.0,
+                                beetle_angle: 0.0,
+                                timestamp: ctx.start_time.elapsed().as_millis() as f64 / 1000f64,
+                                acceleration: 0.0,
+                                map_angle: 0.0,
+                            });
+                            let track = &ctx.selected_course.clone().unwrap().name;
+                            let latest_laptime = ctx.checkpoint_times.last().unwrap().as_millis() as u64;
+                            let mut logs = LogStore::load()?;
+                            let stored = logs.save(track, ctx.racer_name(), &race_log, latest_laptime, true)?;
+                            let logfilepath = logs.path(&stored);
+                            // pb still holds the times from before this run, so a new PB isn't compared against itself
+                            analysis = Some(RaceAnalysis::new(&ctx.checkpoint_times, &race_log, pb.as_ref()));
+                            let racelap = update_track_data(&ctx.checkpoint_times, String::from(format!("./data/splits/{}.toml", track))).context("Failed to export splits")?;
+                            pb = Some(racelap.clone());
+                            ghost = Ghost::load(track, racelap.pb_laptime)?;
+                            record_attempt(&ctx, &race_log, &mut history, RunStatus::Finished, Some(logfilepath.clone()))?;
+                            if *ctx.selected_cup.as_ref().unwrap() != "CUSTOM TRACKS".to_string() {
+                                // uploaded under the name logs had before the log store, rather than the store's path
+                                upload_response = beetlerank.post_log(ctx.racer_name().clone(), track.clone(), logfilepath, format!("{}_{}.csv", track, latest_laptime))?;
+                            }
+                            let (mut removed, missing, _) = logs.apply(&RetentionPolicy::load()?).context("Failed to apply log retention policy")?;
+                            removed.extend(missing);
+                            if !removed.is_empty() {
+                                RunHistory::forget_deleted_logs(&logs, &removed)?;
+                                history = RunHistory::load(track)?;
+                            }
                         }
                     },
                     _ => {},
@@ -118,52 +188,97 @@ pub fn run() -> Result<()> {
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
+                if key.kind == KeyEventKind::Press && state == ProgramState::TrackCreator && editor.is_prompting() {
+                    editor.handle_prompt_key(key.code)?;
+                } else if key.kind == KeyEventKind::Press {
                     match key.code {
-                        KeyCode::Char('q') => state = ProgramState::Quit,
+                        KeyCode::Char('q') => {
+                            record_attempt(&ctx, &race_log, &mut history, RunStatus::Invalid, None)?;
+                            state = ProgramState::Quit;
+                        },
                         KeyCode::Char('r') => { match state {
                             ProgramState::Speedometer => {
+                                record_attempt(&ctx, &race_log, &mut history, RunStatus::Reset, None)?;
                                 ctx.restart_course();
                                 race_log = Vec::new();
                             },
-                            ProgramState::TrackCreator => { 
-                                creating_course.checkpoints = Vec::new();
-                                creating_course.reset = None;
-                            },
+                            ProgramState::TrackCreator => editor.clear(),
                             _ => {},
                             }
                         },
-                        KeyCode::Char('R') => match state {
-                            ProgramState::TrackCreator => {
-                                creating_course.reset = Some(Checkpoint{
-                                    step: -1,
-                                    stepname: Stepname::Reset,
-                                    x: ctx.x(),
-                                    y: ctx.y(),
-                                    z: ctx.z(),
-                                    radius: 15i32,
-                                })
-                            },
-                            _ => {},
-                        }
                         KeyCode::Char('d') => DEBUG.set(!DEBUG.get()),
                         KeyCode::Char('c') => { state = match state {
                             ProgramState::Speedometer => ProgramState::TrackCreator,
-                            ProgramState::TrackCreator => ProgramState::Speedometer,
-                            _ => state
-                        }},
-                        KeyCode::Char('n') => { match state {
                             ProgramState::TrackCreator => {
-                                creating_course.push_cp(ctx.x(), ctx.y(), ctx.z(), 15i32);
+                                editor.stop_recording();
+                                ProgramState::Speedometer
                             },
-                            _ => {},
+                            _ => state
                         }},
-                        KeyCode::Char('e') => { match state {
-                            ProgramState::TrackCreator => {
-                                std::fs::create_dir_all("data/custom_courses")?;
-                                creating_course.export(format!("data/custom_courses/created-course.csv"))?;
-                            },
-                            _ => {},
+                        KeyCode::Char('R') if state == ProgramState::TrackCreator => editor.set_reset(ctx.position()),
+                        KeyCode::Char('n') if state == ProgramState::TrackCreator => editor.push(ctx.position()),
+                        KeyCode::Char('i') if state == ProgramState::TrackCreator => editor.insert_before(ctx.position()),
+                        KeyCode::Char('x') | KeyCode::Delete if state == ProgramState::TrackCreator => editor.delete_selected(),
+                        KeyCode::Char('m') if state == ProgramState::TrackCreator => editor.move_selected(ctx.position()),
+                        KeyCode::Char('+') if state == ProgramState::TrackCreator => editor.adjust_radius(1),
+                        KeyCode::Char('-') if state == ProgramState::TrackCreator => editor.adjust_radius(-1),
+                        KeyCode::Char('s') if state == ProgramState::TrackCreator => editor.mark_start(),
+                        KeyCode::Char('f') if state == ProgramState::TrackCreator => editor.mark_end(),
+                        KeyCode::Char('u') if state == ProgramState::TrackCreator => editor.undo(),
+                        KeyCode::Char('y') if state == ProgramState::TrackCreator => editor.redo(),
+                        KeyCode::Up if state == ProgramState::TrackCreator => editor.select_prev(),
+                        KeyCode::Down if state == ProgramState::TrackCreator => editor.select_next(),
+                        KeyCode::Char('e') if state == ProgramState::TrackCreator => editor.start_saving(),
+                        KeyCode::Char('o') if state == ProgramState::TrackCreator => editor.start_opening()?,
+                        KeyCode::Char('a') if state == ProgramState::TrackCreator => editor.toggle_recording(),
+                        KeyCode::Char('v') if state == ProgramState::TrackCreator => editor.reverse(),
+                        KeyCode::Char('m') if state == ProgramState::Speedometer => {
+                            if let Some(course) = &ctx.selected_course {
+                                let mut pack = MarkerPack::new(course, ctx.map_id());
+                                if !race_log.is_empty() {
+                                    pack = pack.with_trail(Trail::from_log(ctx.map_id(), &race_log));
+                                }
+                                let stem = pack.file_stem();
+                                pack.export(format!("data/markers/{}/{}.xml", stem, stem)).context("Failed to export marker pack")?;
+                            }
+                        },
+                        KeyCode::Tab if state == ProgramState::Speedometer => {
+                            comparison = comparison.next();
+                            if let (Comparison::BeetlerankTop, Some(course)) = (comparison, &ctx.selected_course) {
+                                if top_run.as_ref().is_none_or(|(track, _)| *track != course.name) {
+                                    top_run = Some((course.name.clone(), top_run_splits(&ctx, &mut beetlerank)));
+                                }
+                            }
+                        },
+                        KeyCode::Char('p') if state == ProgramState::Speedometer => {
+                            if let (Some(_), Some(course)) = (ctx.practice, &ctx.selected_course) {
+                                pb = RaceLap::import(&format!("data/splits/{}.toml", course.name))?;
+                                ctx.set_practice(None);
+                            } else if let Some(course) = &ctx.selected_course {
+                                record_attempt(&ctx, &race_log, &mut history, RunStatus::Invalid, None)?;
+                                practice_selector = PracticeSelector::new(course.checkpoints.len());
+                                state = ProgramState::Practice;
+                            }
+                        },
+                        KeyCode::Up if state == ProgramState::Practice => practice_selector.increase(),
+                        KeyCode::Down if state == ProgramState::Practice => practice_selector.decrease(),
+                        KeyCode::Left | KeyCode::Right if state == ProgramState::Practice => practice_selector.toggle_field(),
+                        KeyCode::Esc if state == ProgramState::Practice => state = ProgramState::Speedometer,
+                        KeyCode::Enter if state == ProgramState::Practice => {
+                            if let (Some((from, to)), Some(course)) = (practice_selector.range(), &ctx.selected_course) {
+                                pb = PracticeLaps::load(&course.name)?.get(from, to).cloned();
+                                ctx.set_practice(Some((from, to)));
+                                race_log = Vec::new();
+                            }
+                            state = ProgramState::Speedometer;
+                        },
+                        KeyCode::Char('s') if state == ProgramState::Speedometer => state = ProgramState::Statistics,
+                        KeyCode::Char('z') if state == ProgramState::Speedometer => minimap.toggle_follow(),
+                        KeyCode::Char('s') if state == ProgramState::Statistics => state = ProgramState::Speedometer,
+                        KeyCode::Char('h') => { state = match state {
+                            ProgramState::Speedometer => ProgramState::History,
+                            ProgramState::History => ProgramState::Speedometer,
+                            _ => state
                         }},
                         KeyCode::Char('t') => {state = match state {
                             ProgramState::Speedometer => ProgramState::TrackSelector,
@@ -195,9 +310,18 @@ pub fn run() -> Result<()> {
                                 trackselstate = TrackSelectorState::SelectTrack;
                             },
                             TrackSelectorState::SelectTrack => {
+                                record_attempt(&ctx, &race_log, &mut history, RunStatus::Invalid, None)?;
+                                ctx.set_practice(None);
                                 ctx.load_course(&selected)?;
+                                history = RunHistory::load(&selected)?;
+                                top_run = None;
+                                comparison = Comparison::PersonalBest;
                                 std::fs::create_dir_all("data/splits")?;
-                                pb = RaceLap::import(&format!("data/splits/{}.toml", selected))?;                         
+                                pb = RaceLap::import(&format!("data/splits/{}.toml", selected))?;
+                                ghost = match &pb {
+                                    Some(lap) => Ghost::load(&selected, lap.pb_laptime)?,
+                                    None => None,
+                                };                         
                                 state = ProgramState::Speedometer;
                             }
                             _ => {},
@@ -225,20 +349,44 @@ pub fn run() -> Result<()> {
                 println!("Racer: {}", &ctx.racer_name());
                 println!("---");
             }
-            cup_window = beetlestatelist.viewport().pad(1).border(feotui::BorderStyle::Bold);
+            cup_window = beetlestatelist.viewport();
+            if let (TrackSelectorState::SelectTrack, Some(track)) = (&trackselstate, beetlestatelist.selected()) {
+                if preview.as_ref().is_none_or(|(name, _)| name != track) {
+                    preview = Some((track.clone(), course_stats(track)));
+                }
+                if let Some((_, Some(stats))) = &preview {
+                    cup_window.append(&mut course_preview(stats));
+                }
+            }
+            cup_window = cup_window.pad(1).border(feotui::BorderStyle::Bold);
             if let None = &ctx.selected_course {
                 println!("{}", cup_window.pad(1).border(feotui::BorderStyle::Bold).render());
             } else {
-                let primary_window = speedometer(&mut ctx, &mut beetlerank, &pb, state)?.pad(1).border(feotui::BorderStyle::Bold);
+                let fingerprint = ctx.selected_course.as_ref().unwrap().fingerprint();
+                let top = top_run.as_ref().and_then(|(_, splits)| splits.as_deref());
+                // history and Beetlerank runs cover the whole course, so only the practice bests compare while practicing
+                let compare = match ctx.practice {
+                    Some(_) => comparison.splits(pb.as_ref(), &RunHistory::default(), &fingerprint, None),
+                    None => comparison.splits(pb.as_ref(), &history, &fingerprint, top),
+                };
+                let mut primary_window = speedometer(&mut ctx, &mut beetlerank, &pb, state, comparison, compare.as_deref(), ghost_delta)?;
+                // the PB line is the better guide, but the reference line still helps on tracks without a PB
+                let line = ghost.as_ref().map(|ghost| ghost.path()).or(ctx.reference_line.as_ref());
+                primary_window.append(&mut live_charts(&race_log, &delta_trace));
+                primary_window.append(&mut minimap.view(ctx.selected_course.as_ref().unwrap(), ctx.progress.current_checkpoint, &ctx.position(), ctx.heading(), line));
+                let primary_window = primary_window.pad(1).border(feotui::BorderStyle::Bold);
                 println!("{}", match state {
                     ProgramState::Speedometer => {
                         match ctx.race_state {
-                            RaceState::Finished => primary_window.popup(&race_finished(&ctx, &mut beetlerank, &pb, &upload_response)?.pad(1).border(feotui::BorderStyle::Bold), 2, 2).render(),
+                            RaceState::Finished => primary_window.popup(&race_finished(&ctx, &mut beetlerank, &pb, &upload_response, analysis.as_ref())?.pad(1).border(feotui::BorderStyle::Bold), 2, 2).render(),
                             _ => primary_window.render()
                         }
                     },
-                    ProgramState::TrackCreator => primary_window.popup(&track_creator(&creating_course).pad(1).border(feotui::BorderStyle::Bold), 2, 2).render(),
+                    ProgramState::TrackCreator => primary_window.popup(&track_creator(&editor, &ctx.position()).pad(1).border(feotui::BorderStyle::Bold), 2, 2).render(),
                     ProgramState::TrackSelector => primary_window.popup(&cup_window, 2, 2).render(),
+                    ProgramState::Practice => primary_window.popup(&practice_selector.view().pad(1).border(feotui::BorderStyle::Bold), 2, 2).render(),
+                    ProgramState::Statistics => primary_window.popup(&statistics(&CourseStatistics::new(&history, &fingerprint, ctx.selected_course.as_ref().unwrap().checkpoints.len())).pad(1).border(feotui::BorderStyle::Bold), 2, 2).render(),
+                    ProgramState::History => primary_window.popup(&run_history(&history).pad(1).border(feotui::BorderStyle::Bold), 2, 2).render(),
                     _ => {String::new()},
                 });
             }
@@ -252,41 +400,239 @@ pub fn run() -> Result<()> {
     Ok(())
 }
 
-fn race_finished(ctx: &RaceContext, beetlerank: &mut BeetleRank, pb: &Option<RaceLap>, upload_response: &Vec<String>) -> Result<Vec<String>> {
+fn race_finished(ctx: &RaceContext, beetlerank: &mut BeetleRank, pb: &Option<RaceLap>, upload_response: &Vec<String>, analysis: Option<&RaceAnalysis>) -> Result<Vec<String>> {
     let mut lines: Vec<String> = Vec::new();
     let track = &ctx.selected_course.as_ref().unwrap().name;
     lines.push("Race Finished!".to_string());
     let laptime = ctx.checkpoint_times.last().unwrap();
-    if ctx.selected_cup != Some("CUSTOM TRACKS".to_string()) {
+    if ctx.practice.is_none() && ctx.selected_cup != Some("CUSTOM TRACKS".to_string()) {
         if let Some(you) = &beetlerank.rankings[track].you {
             let best_time = (you[1].laptime * 1000f64) as u64;
             lines.push(format!("Beetlerank Best Time: {}", Duration::from_millis(best_time).timestamp()));
         }
     }
     if let Some(rl) = pb {
-        lines.push(format!("Local Best Time: {}", Duration::from_millis(rl.pb_laptime).timestamp()));
+        lines.push(format!("{}: {}", if ctx.practice.is_some() { "Practice Best Time" } else { "Local Best Time" }, Duration::from_millis(rl.pb_laptime).timestamp()));
     }
     
     lines.push(format!("Lap Time: {}", laptime.timestamp()));
-    if *upload_response != Vec::<String>::new() {
+    if ctx.practice.is_none() && *upload_response != Vec::<String>::new() {
         lines.append(&mut upload_response.clone());
     }
+    if let Some(analysis) = analysis {
+        lines.append(&mut race_analysis(analysis));
+    }
     Ok(lines)
 }
 
-fn track_creator(course: &Course) -> Vec<String> {
+/// How far back the speed sparkline goes
+const SPEED_WINDOW: Duration = Duration::from_secs(10);
+/// Columns of the live charts, matching the minimap
+const CHART_WIDTH: usize = 40;
+const DELTA_CHART_HEIGHT: usize = 5;
+
+/// Speed over the last few seconds and the delta to the PB ghost over the lap so far
+fn live_charts(race_log: &[RaceLogEntry], delta_trace: &[Option<f32>]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let Some(latest) = race_log.last() else {
+        return lines
+    };
+    let recent: Vec<f32> = race_log.iter()
+        .filter(|entry| entry.timestamp >= latest.timestamp - SPEED_WINDOW.as_secs_f64())
+        .map(|entry| entry.speed)
+        .collect();
+    let top = recent.iter().copied().fold(0f32, f32::max);
+    lines.push(format!("Speed (last {}s): {} {:.0}", SPEED_WINDOW.as_secs(), feotui::sparkline(&recent, CHART_WIDTH), top));
+    let deltas: Vec<f32> = delta_trace.iter().flatten().copied().collect();
+    if !deltas.is_empty() {
+        lines.push("Delta to PB ghost (s):".to_string());
+        lines.append(&mut feotui::line_chart(&deltas, CHART_WIDTH, DELTA_CHART_HEIGHT));
+    }
+    lines
+}
+
+/// Timestamp in green when it beats the PB and red when it doesn't
+fn against_pb(time: Duration, pb: Duration) -> String {
+    match time.cmp(&pb) {
+        std::cmp::Ordering::Less => time.timestamp().green().to_string(),
+        std::cmp::Ordering::Greater => time.timestamp().red().to_string(),
+        std::cmp::Ordering::Equal => time.timestamp(),
+    }
+}
+
+/// Formats a difference in milliseconds as a timestamp with its sign in front
+fn signed_timestamp(delta: i64) -> String {
+    format!("{}{}", if delta < 0 { "-" } else { "+" }, Duration::from_millis(delta.unsigned_abs()).timestamp())
+}
+
+fn race_analysis(analysis: &RaceAnalysis) -> Vec<String> {
+    let signed = |delta: Option<i64>| delta.map_or(String::new(), signed_timestamp);
+    let speed = |speed: Option<f32>| speed.map_or(String::from("-"), |speed| format!("{:.1}", speed));
+    let mut lines: Vec<String> = Vec::new();
+    lines.push("----- Analysis -----".to_string());
+    lines.push(format!("{: >2} {: <9} {: <10} {: <10} {: >6} {: >6} {: >6} {: >6}", "CP", "Split", "PB", "Gold", "Entry", "Min", "Avg", "Max"));
+    for segment in analysis.segments.iter() {
+        lines.push(format!("{: >2} {: <9} {: <10} {: <10} {: >6} {: >6} {: >6} {: >6}",
+            segment.checkpoint,
+            Duration::from_millis(segment.split).timestamp(),
+            signed(segment.pb_delta),
+            signed(segment.gold_delta),
+            speed(segment.entry_speed),
+            speed(segment.min_speed),
+            speed(segment.avg_speed),
+            speed(segment.max_speed)));
+    }
+    lines.push("----- Techniques -----".to_string());
+    lines.push(format!("{: >2} {}", "CP", Technique::ALL.iter().map(|technique| format!("{: <11}", technique)).join(" ")));
+    let techniques = |summary: &TechniqueSummary| Technique::ALL.iter()
+        .map(|technique| summary.get(*technique))
+        .map(|count| format!("{: <11}", format!("{}x {:.1}s", count.count, count.duration as f64 / 1000f64)))
+        .join(" ");
+    for segment in analysis.segments.iter() {
+        lines.push(format!("{: >2} {}", segment.checkpoint, techniques(&segment.techniques)));
+    }
+    lines.push(format!("{: >2} {}", "", techniques(&analysis.techniques)));
+    let losses = analysis.biggest_losses(3);
+    if !losses.is_empty() {
+        lines.push("----- Most time lost -----".to_string());
+        for segment in losses {
+            lines.push(format!("Checkpoint {: >2}: {}", segment.checkpoint, signed(segment.pb_delta)));
+        }
+    }
+    lines
+}
+
+/// Saves the attempt in progress to the run history
+///
+/// Nothing is saved unless the racer has crossed the start, since there is no attempt to save.
+/// Practice runs only cover part of the course, so they are left out too. Unfinished attempts
+/// have their race log stored as invalid, so they can still be looked at later.
+fn record_attempt(ctx: &RaceContext, race_log: &[RaceLogEntry], history: &mut RunHistory, status: RunStatus, log_path: Option<String>) -> Result<()> {
+    let started = match status {
+        RunStatus::Finished => ctx.race_state == RaceState::Finished,
+        _ => ctx.race_state == RaceState::Racing,
+    };
+    if let (true, None, Some(course)) = (started, ctx.practice, &ctx.selected_course) {
+        let log_path = match log_path {
+            None if status != RunStatus::Finished && !race_log.is_empty() => {
+                let mut logs = LogStore::load()?;
+                let laptime = ctx.checkpoint_times.last().map_or(0, |time| time.as_millis() as u64);
+                let stored = logs.save(&course.name, ctx.racer_name(), race_log, laptime, false)?;
+                Some(logs.path(&stored))
+            },
+            log_path => log_path,
+        };
+        history.append(RunRecord::new(course, ctx.racer_name(), &ctx.checkpoint_times, status, log_path).with_elapsed(ctx.start_time.elapsed()).with_techniques(race_log)).context("Failed to save run history")?;
+    }
+    Ok(())
+}
+
+fn run_history(history: &RunHistory) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let finished = history.query(&RunFilter { status: Some(RunStatus::Finished), ..Default::default() }).len();
+    lines.push(format!("Run History: {} attempts, {} finished", history.runs.len(), finished));
+    lines.push("-------------".to_string());
+    for run in history.query(&RunFilter { limit: Some(15), ..Default::default() }).iter().rev() {
+        let result = match run.laptime() {
+            Some(laptime) => Duration::from_millis(laptime).timestamp(),
+            None => format!("CP {}", run.checkpoint_times.len().saturating_sub(1)),
+        };
+        lines.push(format!("{} {: <8} {: <9} {}", run.date.format("%Y-%m-%d %H:%M"), run.status, result, run.character));
+    }
+    if let Some(run) = history.runs.iter().rev().find(|run| run.techniques.is_some()) {
+        lines.push(format!("----- Techniques, {} -----", run.date.format("%Y-%m-%d %H:%M")));
+        lines.push(format!("Lap: {}", run.techniques.unwrap_or_default()));
+        for (idx, segment) in run.segment_techniques.iter().enumerate() {
+            lines.push(format!("CP {: >2}: {}", idx + 1, segment));
+        }
+    }
+    lines
+}
+
+/// Longest bar drawn in the reset heatmap
+const HEATMAP_WIDTH: usize = 20;
+
+fn statistics(stats: &CourseStatistics) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    lines.push("Statistics".to_string());
+    lines.push("-------------".to_string());
+    lines.push(format!("Attempts: {}", stats.attempts));
+    lines.push(format!("Finished: {} ({:.1}%)", stats.finished, stats.completion_rate() * 100f32));
+    lines.push(format!("Resets: {}", stats.resets));
+    lines.push(format!("Time spent on reset runs: {}", stats.total_wasted.timestamp()));
+    lines.push("----- Resets after each checkpoint -----".to_string());
+    let most = stats.resets_by_checkpoint.iter().max().copied().unwrap_or(0).max(1);
+    for (idx, (resets, wasted)) in stats.resets_by_checkpoint.iter().zip(stats.time_wasted.iter()).enumerate() {
+        let bar = "█".repeat((resets * HEATMAP_WIDTH).div_ceil(most));
+        lines.push(format!("Checkpoint: {: >2} {: <width$} {: >3}, Time wasted: {}", idx, bar, resets, wasted.timestamp(), width = HEATMAP_WIDTH));
+    }
+    lines
+}
+
+fn track_creator(editor: &CourseEditor, position: &Position) -> Vec<String> {
     let mut lines: Vec<String> = Vec::new();
     lines.push("Track Creator".to_string());
     lines.push("-------------".to_string());
-    for cp in &course.checkpoints {
-        lines.push(format!("CP {}: ({:.2}, {:.2}, {:.2}", cp.step, cp.x, cp.y, cp.z));
+    for (idx, cp) in editor.course.checkpoints.iter().enumerate() {
+        let marker = if editor.selected == Some(idx) { "*" } else { " " };
+        lines.push(format!("{}{: <5} {: >2}: ({:.2}, {:.2}, {:.2}) r={}", marker, cp.stepname, cp.step, cp.x, cp.y, cp.z, cp.radius));
+    }
+    if let Some(reset) = &editor.course.reset {
+        lines.push(format!(" Reset: ({:.2}, {:.2}, {:.2}) r={}", reset.x, reset.y, reset.z, reset.radius));
+    }
+    lines.push("-------------".to_string());
+    if let Some(distance) = editor.distance_to_selected(position) {
+        lines.push(format!("Distance to selected: {:.4}", distance));
+    }
+    if editor.recorder.is_some() {
+        lines.push("Recording checkpoints as you drive (a: stop)".to_string());
+    }
+    if let Some(status) = &editor.status {
+        lines.push(status.clone());
     }
-    if let Some(reset) = &course.reset {
-        lines.push(format!("Reset: ({:.2}, {:.2}, {:.2}", reset.x, reset.y, reset.z));
+    match &editor.mode {
+        CreatorMode::Editing => {
+            lines.push("n: add  i: insert before  m: move here  x: delete".to_string());
+            lines.push("+/-: radius  s: start  f: end  R: reset here".to_string());
+            lines.push("u: undo  y: redo  r: clear  e: save  o: open".to_string());
+            lines.push("a: place checkpoints while driving  v: reverse".to_string());
+        },
+        CreatorMode::Naming(name) => {
+            lines.push(format!("Save as: {}_", name));
+            lines.push("Enter: save  Esc: cancel".to_string());
+        },
+        CreatorMode::ConfirmOverwrite(name) => {
+            lines.push(format!("{} already exists. Overwrite it? (y/n)", name));
+        },
+        CreatorMode::Opening(list) => {
+            lines.push("Open course (Enter: open  Esc: cancel)".to_string());
+            lines.append(&mut list.viewport());
+        },
     }
     lines
 }
 
+/// Measures a course that has already been downloaded, without reaching out to Beetlerank
+fn course_stats(track: &str) -> Option<CourseStats> {
+    let path = format!("data/courses/{}.csv", track);
+    if !Path::new(&path).is_file() {
+        return None
+    }
+    Course::from_path(&path).ok().map(|course| CourseStats::new(&course))
+}
+
+fn course_preview(stats: &CourseStats) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    lines.push("---".to_string());
+    lines.push(format!("Length: {:.0} (straight line {:.0})", stats.path_length, stats.straight_length));
+    lines.push(format!("Climb: +{:.0} / -{:.0}", stats.elevation_gain, stats.elevation_loss));
+    if let Some((idx, angle)) = stats.tightest_turn {
+        lines.push(format!("Tightest turn: {:.0}° at checkpoint {}", angle, idx));
+    }
+    lines.push(format!("Expected time: {}", stats.expected_time(DEFAULT_AVERAGE_SPEED).timestamp()));
+    lines
+}
+
 fn rank(ctx: &mut RaceContext, beetlerank: &mut BeetleRank) -> Result<Vec<String>> {
     let mut lines: Vec<String> = Vec::new();
     let ranks = beetlerank.get_rank(&ctx.selected_course.as_ref().unwrap().name, &ctx.racer_name())?;
@@ -314,7 +660,29 @@ fn rank(ctx: &mut RaceContext, beetlerank: &mut BeetleRank) -> Result<Vec<String
     Ok(lines)
 }
 
-fn speedometer(ctx: &mut RaceContext, beetlerank: &mut BeetleRank, pb: &Option<RaceLap>, state: ProgramState) -> Result<Vec<String>> {
+/// Splits of the #1 ranked run on Beetlerank, found by retiming its racelog against the selected course
+fn top_run_splits(ctx: &RaceContext, beetlerank: &mut BeetleRank) -> Option<Vec<u64>> {
+    let course = ctx.selected_course.as_ref()?;
+    if ctx.selected_cup == Some("CUSTOM TRACKS".to_string()) {
+        return None
+    }
+    let top = beetlerank.get_rank(&course.name, ctx.racer_name()).ok()?.top_3.first()?.clone();
+    let log = match BeetleRank::get_log(&top).and_then(|data| parse_racelog(&data)) {
+        Ok(log) => log,
+        Err(e) => {
+            log::warn!("Failed to download the Beetlerank #1 run for {}: {}", course.name, e);
+            return None
+        },
+    };
+    let retime = Retime::new(course, ctx.course_settings.missed_checkpoint, &log);
+    let times = retime.finished_times()?;
+    Some(times.windows(2).map(|w| w[1].saturating_sub(w[0]).as_millis() as u64).collect())
+}
+
+/// Milliseconds between flashes of the missed checkpoint warning
+const MISSED_WARNING_FLASH: u128 = 300;
+
+fn speedometer(ctx: &mut RaceContext, beetlerank: &mut BeetleRank, pb: &Option<RaceLap>, state: ProgramState, comparison: Comparison, compare: Option<&[u64]>, ghost_delta: Option<GhostDelta>) -> Result<Vec<String>> {
     let mut lines: Vec<String> = Vec::new();
     lines.push(format!("Track: {}", ctx.selected_course.as_ref().unwrap().name));
     
@@ -323,39 +691,85 @@ fn speedometer(ctx: &mut RaceContext, beetlerank: &mut BeetleRank, pb: &Option<R
     }
     
     lines.push(format!("---"));
-    lines.push(format!("Checkpoint: {}", ctx.current_checkpoint));
-    lines.push(format!("Distance to next checkpoint: {:.4}", if ctx.current_checkpoint < ctx.selected_course.as_ref().unwrap().checkpoints.len() {
+    lines.push(format!("Checkpoint: {}", ctx.progress.current_checkpoint));
+    lines.push(format!("Distance to next checkpoint: {:.4}", if ctx.progress.current_checkpoint < ctx.selected_course.as_ref().unwrap().checkpoints.len() {
         ctx.current_cp_distance()} else {
             -1.0
         }));
+    if let Some(bearing) = ctx.next_checkpoint_bearing() {
+        let turn = match bearing.angle.round() as i32 {
+            0 => String::from("ahead"),
+            angle => format!("{}° {}", angle.abs(), if angle > 0 { "right" } else { "left" }),
+        };
+        lines.push(format!("Next checkpoint: {} {}, {:.1} {}", bearing.arrow(), turn, bearing.height.abs(), if bearing.height < 0f32 { "below" } else { "above" }));
+    }
+    if ctx.is_wrong_way() {
+        lines.push("!!! WRONG WAY !!!".to_string());
+    }
+    if let Some((missed, since)) = ctx.missed_checkpoint() {
+        let warning = match ctx.course_settings.missed_checkpoint {
+            MissedCheckpointPolicy::Recover => format!("!!! MISSED CHECKPOINT {}, GO BACK !!!", missed),
+            MissedCheckpointPolicy::Void => format!("!!! MISSED CHECKPOINT {}, RUN VOID !!!", missed),
+        };
+        lines.push(match (since.as_millis() / MISSED_WARNING_FLASH) % 2 {
+            0 => warning.black().on_red().to_string(),
+            _ => warning.red().to_string(),
+        });
+    }
     lines.push(format!("Distance to reset checkpoint: {:.4}", ctx.reset_cp_distance().unwrap_or(-1.0)));
+    if let Some(distance) = ctx.reference_line_distance() {
+        lines.push(format!("Distance to reference line: {:.4}", distance));
+    }
     lines.push(format!("Speed: {:?}", ctx.filtered_speed()));
+    if let Some((from, to)) = ctx.practice {
+        lines.push(format!("Practicing checkpoint {} to {} (p to stop)", from, to));
+    }
+    if let Some(livesplit) = &ctx.livesplit {
+        lines.push(format!("LiveSplit: {}", if livesplit.is_connected() { "Connected" } else { "Not connected" }));
+    }
     if let Some(rl) = pb {
         lines.push(format!("Personal Best: {}", Duration::from_millis(rl.pb_laptime).timestamp()));
-        lines.push(format!("Sum of Best: {}", Duration::from_millis(rl.splits.best.iter().sum()).timestamp()))
+        lines.push(format!("Sum of Best: {}", Duration::from_millis(rl.splits.best.iter().sum()).timestamp()));
+        if ctx.race_state == RaceState::Racing {
+            if let Some(best) = live_projection(&rl.splits.best, &ctx.checkpoint_times, ctx.start_time.elapsed()) {
+                lines.push(format!("Best Possible Time: {}", against_pb(best, Duration::from_millis(rl.pb_laptime))));
+            }
+        }
+    }
+    if let Some(delta) = ghost_delta {
+        lines.push(format!("PB Ghost: {:+.3}s, {:.1} units {}", delta.time, delta.distance.abs(), if delta.distance < 0f32 { "behind" } else { "ahead" }));
     }
-    if let Some(c) = &ctx.selected_course {
-        lines.push("----- Checkpoint Times -----".to_string());
-        for idx in 1..c.checkpoints.len() {
+    // keeps moving between checkpoints while racing, so a slow segment shows before it's over
+    let projection = compare.and_then(|splits| match ctx.race_state {
+        RaceState::Racing => live_projection(splits, &ctx.checkpoint_times, ctx.start_time.elapsed()),
+        _ => projected_final(splits, &ctx.checkpoint_times),
+    });
+    if let Some(final_time) = projection {
+        let final_time = pb.as_ref().map_or(final_time.timestamp(), |rl| against_pb(final_time, Duration::from_millis(rl.pb_laptime)));
+        lines.push(format!("Projected Final vs {}: {}", comparison, final_time));
+    }
+    if ctx.selected_course.is_some() {
+        lines.push(format!("----- Checkpoint Times vs {} (Tab to change) -----", comparison));
+        let (first, last) = ctx.checkpoint_range();
+        let time_saves = pb.as_ref().map(possible_time_save).unwrap_or_default();
+        for checkpoint in first + 1..=last {
+            // times are counted from the first checkpoint of the run, which is only the start when not practicing
+            let idx = checkpoint - first;
             let blank = Duration::new(0,0);
             let dur = ctx.checkpoint_times.get(idx).unwrap_or(&blank);
-            let cpdelta = dur.saturating_sub(*ctx.checkpoint_times.get(idx.saturating_sub(1)).unwrap_or(&Duration::new(0,0)));
-            let mut delta: String = String::new();
-            if let Some(lap) = pb {
-                // BUG: since the pb is updated immediately, then reloaded immediately, the delta will suddenly be 00:00:000 when finishing a lap with a new best time
-                if let Some(split) = lap.splits.pb.get(idx.saturating_sub(1)) {
-                    if cpdelta == blank {
-                        delta = "".to_string();
-                    }
-                    else if *split > cpdelta.as_millis() as u64 {
-                        delta = format!("-{}", Duration::from_millis(split.saturating_sub(cpdelta.as_millis() as u64)).timestamp())
-                    } else {
-                        delta = format!("+{}", Duration::from_millis((cpdelta.as_millis() as u64).saturating_sub(*split)).timestamp())
-                    }
-                }
-            }
+            // BUG: since the pb is updated immediately, then reloaded immediately, the delta will suddenly be 00:00:000 when finishing a lap with a new best time
+            let delta = compare.and_then(|splits| segment_delta(splits, &ctx.checkpoint_times, idx)).map_or(String::new(), signed_timestamp);
         
-            lines.push(format!("Checkpoint: {: >2}, Time: {: <9}, PB: {: <9}", idx, if *dur > blank { dur.timestamp() } else { "".to_string() }, delta));
+            // the time save stays last on the line, since colours throw off the padding of anything after it
+            let save = time_saves.get(idx - 1).map_or(String::new(), |save| {
+                let save = Duration::from_millis(save.unsigned_abs()).timestamp();
+                match pb.as_ref().and_then(|rl| segment_delta(&rl.splits.pb, &ctx.checkpoint_times, idx)) {
+                    Some(delta) if delta < 0 => save.green().to_string(),
+                    Some(delta) if delta > 0 => save.red().to_string(),
+                    _ => save,
+                }
+            });
+            lines.push(format!("Checkpoint: {: >2}, Time: {: <9}, Delta: {: <9}, Save: {}", checkpoint, if *dur > blank { dur.timestamp() } else { "".to_string() }, delta, save));
         }
     }
     Ok(lines)
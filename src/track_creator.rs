@@ -0,0 +1,452 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use crossterm::event::KeyCode;
+use feotui::StatefulScrollingList;
+use itertools::Itertools;
+
+use crate::speedometer::{autoplace::{AutoPlaceSettings, AutoPlacer}, checkpoint::{Checkpoint, Stepname, DEFAULT_RADIUS}, course::Course, guild_wars_handler::Position, util::euclidian_distance_3d};
+
+/// Smallest radius a checkpoint can be shrunk to in the editor
+const MIN_RADIUS: i32 = 1;
+
+/// Folder holding every course, both cached from Beetlerank and custom made
+pub const COURSE_DIR: &str = "data/courses";
+
+/// Folder that the "CUSTOM TRACKS" cup lists courses from
+pub const CUSTOM_COURSE_DIR: &str = "data/courses/custom_courses";
+
+/// What the Track Creator is currently waiting on from the keyboard
+pub enum CreatorMode {
+    Editing,
+    /// Typing the name to save the course under
+    Naming(String),
+    /// Waiting for a yes or no before replacing an existing custom course
+    ConfirmOverwrite(String),
+    /// Picking a custom or cached course to open
+    Opening(StatefulScrollingList<String>),
+}
+
+/// CourseEditor holds the course being built in the Track Creator along with the selected
+/// checkpoint and the undo/redo history
+pub struct CourseEditor {
+    pub course: Course,
+    pub selected: Option<usize>,
+    pub mode: CreatorMode,
+    /// Message about the last save or open, shown until the next one
+    pub status: Option<String>,
+    /// Places checkpoints along the racer's path while recording
+    pub recorder: Option<AutoPlacer>,
+    undo_stack: Vec<(Course, Option<usize>)>,
+    redo_stack: Vec<(Course, Option<usize>)>,
+}
+
+impl CourseEditor {
+    pub fn new() -> CourseEditor {
+        CourseEditor {
+            course: Course::new(),
+            selected: None,
+            mode: CreatorMode::Editing,
+            status: None,
+            recorder: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    /// Whether keys should go to a prompt rather than the usual key bindings
+    pub fn is_prompting(&self) -> bool {
+        !matches!(self.mode, CreatorMode::Editing)
+    }
+
+    /// Asks for a name to save the course under, suggesting the name it was opened with
+    pub fn start_saving(&mut self) {
+        let name = Path::new(&self.course.name).file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        self.mode = CreatorMode::Naming(name);
+    }
+
+    /// Lists the custom and cached courses to pick one to open
+    pub fn start_opening(&mut self) -> Result<()> {
+        let mut list = StatefulScrollingList::with_items(list_courses()?)
+            .with_scroll_style(feotui::ScrollStyle::Scrolling)
+            .with_viewport_length(10);
+        list.select(0);
+        self.mode = CreatorMode::Opening(list);
+        Ok(())
+    }
+
+    /// Handles a key press while a prompt is open
+    pub fn handle_prompt_key(&mut self, code: KeyCode) -> Result<()> {
+        match (&mut self.mode, code) {
+            (_, KeyCode::Esc) => self.mode = CreatorMode::Editing,
+            (CreatorMode::Naming(name), KeyCode::Char(c)) => name.push(c),
+            (CreatorMode::Naming(name), KeyCode::Backspace) => { name.pop(); },
+            (CreatorMode::Naming(name), KeyCode::Enter) => {
+                let name = name.trim().to_string();
+                if let Err(err) = validate_name(&name) {
+                    self.status = Some(err.to_string());
+                } else if Path::new(&custom_course_path(&name)).exists() {
+                    self.mode = CreatorMode::ConfirmOverwrite(name);
+                } else {
+                    self.save(&name)?;
+                }
+            },
+            (CreatorMode::ConfirmOverwrite(name), KeyCode::Char('y')) => {
+                let name = name.clone();
+                self.save(&name)?;
+            },
+            (CreatorMode::ConfirmOverwrite(name), KeyCode::Char('n')) => self.mode = CreatorMode::Naming(name.clone()),
+            (CreatorMode::Opening(list), KeyCode::Up) => list.prev(),
+            (CreatorMode::Opening(list), KeyCode::Down) => list.next(),
+            (CreatorMode::Opening(list), KeyCode::Enter | KeyCode::Right) => {
+                if let Some(track) = list.selected().cloned() {
+                    self.open(&track)?;
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    /// Replaces the course being edited with a saved one
+    ///
+    /// Saving afterwards always writes to the custom library, so opening a Beetlerank course
+    /// and saving it makes an editable copy.
+    pub fn open(&mut self, track: &str) -> Result<()> {
+        let mut course = Course::from_path(&format!("{}/{}.csv", COURSE_DIR, track)).context(format!("Failed to open course {}", track))?;
+        course.name = track.to_string();
+        *self = CourseEditor::new();
+        self.course = course;
+        self.status = Some(format!("Opened {}", track));
+        Ok(())
+    }
+
+    /// Writes the course to the custom library under `name`
+    fn save(&mut self, name: &str) -> Result<()> {
+        save_custom_course(&mut self.course, name)?;
+        self.status = Some(format!("Saved {}", self.course.name));
+        self.mode = CreatorMode::Editing;
+        Ok(())
+    }
+
+    /// Turns the course around so it is driven from the end back to the start
+    pub fn reverse(&mut self) {
+        self.snapshot();
+        self.course = self.course.reversed(&self.course.name);
+        self.selected = self.selected.map(|idx| self.course.checkpoints.len() - 1 - idx);
+    }
+
+    /// Starts or stops placing checkpoints automatically as the racer drives
+    pub fn toggle_recording(&mut self) {
+        self.recorder = match self.recorder {
+            Some(_) => None,
+            None => Some(AutoPlacer::new(AutoPlaceSettings::default())),
+        };
+    }
+
+    /// Stops placing checkpoints, so none are added while the Track Creator isn't on screen
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+    }
+
+    /// Feeds the racer's position to the recorder, adding a checkpoint if one belongs there
+    pub fn record(&mut self, position: Position) {
+        if let Some(point) = self.recorder.as_mut().and_then(|recorder| recorder.sample(position)) {
+            self.push(point);
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.course.checkpoints.is_empty() {
+            return
+        }
+        self.selected = Some(match self.selected {
+            Some(idx) => usize::min(idx + 1, self.course.checkpoints.len() - 1),
+            None => 0,
+        });
+    }
+
+    pub fn select_prev(&mut self) {
+        if self.course.checkpoints.is_empty() {
+            return
+        }
+        self.selected = Some(self.selected.map_or(0, |idx| idx.saturating_sub(1)));
+    }
+
+    pub fn selected_checkpoint(&self) -> Option<&Checkpoint> {
+        self.course.checkpoints.get(self.selected?)
+    }
+
+    /// Adds a checkpoint at the end of the course, in front of the end if one is marked, and
+    /// selects it
+    pub fn push(&mut self, position: Position) {
+        self.snapshot();
+        let checkpoints = &mut self.course.checkpoints;
+        let idx = match checkpoints.last() {
+            Some(last) if last.stepname == Stepname::End => checkpoints.len() - 1,
+            _ => checkpoints.len(),
+        };
+        checkpoints.insert(idx, checkpoint_at(position, DEFAULT_RADIUS));
+        self.course.renumber();
+        self.selected = Some(idx);
+    }
+
+    /// Adds a checkpoint in front of the selected one, or at the end if nothing is selected
+    pub fn insert_before(&mut self, position: Position) {
+        let Some(idx) = self.selected else {
+            return self.push(position)
+        };
+        self.snapshot();
+        self.course.checkpoints.insert(idx, checkpoint_at(position, DEFAULT_RADIUS));
+        self.course.renumber();
+    }
+
+    pub fn delete_selected(&mut self) {
+        let Some(idx) = self.selected else { return };
+        self.snapshot();
+        self.course.checkpoints.remove(idx);
+        self.course.renumber();
+        self.selected = match self.course.checkpoints.len() {
+            0 => None,
+            len => Some(usize::min(idx, len - 1)),
+        };
+    }
+
+    pub fn move_selected(&mut self, position: Position) {
+        let Some(idx) = self.selected else { return };
+        self.snapshot();
+        let cp = &mut self.course.checkpoints[idx];
+        (cp.x, cp.y, cp.z) = (position[0], position[1], position[2]);
+    }
+
+    pub fn adjust_radius(&mut self, delta: i32) {
+        let Some(idx) = self.selected else { return };
+        self.snapshot();
+        let cp = &mut self.course.checkpoints[idx];
+        cp.radius = i32::max(cp.radius + delta, MIN_RADIUS);
+    }
+
+    /// Moves the selected checkpoint to the front of the course and makes it the start
+    pub fn mark_start(&mut self) {
+        let Some(idx) = self.selected else { return };
+        self.snapshot();
+        let mut cp = self.course.checkpoints.remove(idx);
+        cp.stepname = Stepname::Start;
+        for other in self.course.checkpoints.iter_mut().filter(|c| c.stepname == Stepname::Start) {
+            other.stepname = Stepname::Checkpoint;
+        }
+        self.course.checkpoints.insert(0, cp);
+        self.course.renumber();
+        self.selected = Some(0);
+    }
+
+    /// Moves the selected checkpoint to the back of the course and makes it the end
+    pub fn mark_end(&mut self) {
+        let Some(idx) = self.selected else { return };
+        self.snapshot();
+        let mut cp = self.course.checkpoints.remove(idx);
+        cp.stepname = Stepname::End;
+        for other in self.course.checkpoints.iter_mut().filter(|c| c.stepname == Stepname::End) {
+            other.stepname = Stepname::Checkpoint;
+        }
+        self.course.checkpoints.push(cp);
+        self.course.renumber();
+        self.selected = Some(self.course.checkpoints.len() - 1);
+    }
+
+    pub fn set_reset(&mut self, position: Position) {
+        self.snapshot();
+        self.course.add_reset(position[0], position[1], position[2], DEFAULT_RADIUS);
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshot();
+        self.course.checkpoints = Vec::new();
+        self.course.reset = None;
+        self.selected = None;
+    }
+
+    pub fn undo(&mut self) {
+        if let Some((course, selected)) = self.undo_stack.pop() {
+            self.redo_stack.push((std::mem::replace(&mut self.course, course), self.selected));
+            self.selected = selected;
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some((course, selected)) = self.redo_stack.pop() {
+            self.undo_stack.push((std::mem::replace(&mut self.course, course), self.selected));
+            self.selected = selected;
+        }
+    }
+
+    /// Distance from `position` to the selected checkpoint
+    pub fn distance_to_selected(&self, position: &Position) -> Option<f32> {
+        Some(euclidian_distance_3d(position, &self.selected_checkpoint()?.point()))
+    }
+
+    /// Saves the current course so the next change can be undone
+    fn snapshot(&mut self) {
+        self.undo_stack.push((self.course.clone(), self.selected));
+        self.redo_stack.clear();
+    }
+}
+
+pub fn custom_course_path(name: &str) -> String {
+    format!("{}/{}.csv", CUSTOM_COURSE_DIR, name)
+}
+
+/// Writes a course to the custom library under `name`, so it shows up in the "CUSTOM TRACKS" cup
+/// and keeps its own splits
+pub fn save_custom_course(course: &mut Course, name: &str) -> Result<()> {
+    validate_name(name)?;
+    fs::create_dir_all(CUSTOM_COURSE_DIR).context("Failed to create custom_courses directory")?;
+    course.name = format!("custom_courses/{}", name);
+    course.export(custom_course_path(name))
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("The course needs a name");
+    }
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Course names can't contain slashes or start with a dot");
+    }
+    Ok(())
+}
+
+/// Tracks that can be opened in the creator, custom courses first, then courses cached from Beetlerank
+pub fn list_courses() -> Result<Vec<String>> {
+    fs::create_dir_all(CUSTOM_COURSE_DIR).context("Failed to create custom_courses directory")?;
+    let stems = |dir: &str, prefix: &str| -> Result<Vec<String>> {
+        Ok(fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+            .map(|path| format!("{}{}", prefix, path.file_stem().unwrap().to_string_lossy()))
+            .sorted()
+            .collect())
+    };
+    let mut tracks = stems(CUSTOM_COURSE_DIR, "custom_courses/")?;
+    tracks.append(&mut stems(COURSE_DIR, "")?);
+    Ok(tracks)
+}
+
+fn checkpoint_at(position: Position, radius: i32) -> Checkpoint {
+    Checkpoint {
+        step: 0,
+        stepname: Stepname::Checkpoint,
+        x: position[0],
+        y: position[1],
+        z: position[2],
+        radius,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xs(editor: &CourseEditor) -> Vec<f32> {
+        editor.course.checkpoints.iter().map(|cp| cp.x).collect()
+    }
+
+    #[test]
+    fn test_insert_delete() {
+        let mut editor = CourseEditor::new();
+        editor.push([0.0, 0.0, 0.0]);
+        editor.push([2.0, 0.0, 0.0]);
+        editor.insert_before([1.0, 0.0, 0.0]);
+        assert_eq!(xs(&editor), vec![0.0, 1.0, 2.0]);
+        assert_eq!(editor.course.checkpoints.iter().map(|cp| cp.step).collect::<Vec<i16>>(), vec![0, 1, 2]);
+        assert_eq!(editor.selected, Some(1));
+
+        editor.select_next();
+        editor.delete_selected();
+        assert_eq!(xs(&editor), vec![0.0, 1.0]);
+        assert_eq!(editor.selected, Some(1));
+    }
+
+    #[test]
+    fn test_mark_start_end() {
+        let mut editor = CourseEditor::new();
+        editor.push([0.0, 0.0, 0.0]);
+        editor.push([1.0, 0.0, 0.0]);
+        editor.push([2.0, 0.0, 0.0]);
+        editor.mark_start();
+        editor.select_next();
+        editor.mark_end();
+        assert_eq!(xs(&editor), vec![2.0, 1.0, 0.0]);
+        assert_eq!(editor.course.checkpoints[0].stepname, Stepname::Start);
+        assert_eq!(editor.course.checkpoints[2].stepname, Stepname::End);
+        assert_eq!(editor.selected, Some(2));
+    }
+
+    #[test]
+    fn test_push_after_mark_end() {
+        let mut editor = CourseEditor::new();
+        editor.push([0.0, 0.0, 0.0]);
+        editor.push([1.0, 0.0, 0.0]);
+        editor.mark_end();
+        editor.push([2.0, 0.0, 0.0]);
+        assert_eq!(xs(&editor), vec![0.0, 2.0, 1.0]);
+        assert_eq!(editor.course.checkpoints.iter().map(|cp| cp.step).collect::<Vec<i16>>(), vec![0, 1, 2]);
+        assert_eq!(editor.course.checkpoints[2].stepname, Stepname::End);
+        assert_eq!(editor.course.checkpoints[1].stepname, Stepname::Checkpoint);
+        assert_eq!(editor.selected, Some(1));
+    }
+
+    #[test]
+    fn test_stop_recording() {
+        let mut editor = CourseEditor::new();
+        editor.toggle_recording();
+        editor.record([0.0, 0.0, 0.0]);
+        assert_eq!(xs(&editor), vec![0.0]);
+        editor.stop_recording();
+        assert!(editor.recorder.is_none());
+        editor.record([1000.0, 0.0, 0.0]);
+        assert_eq!(xs(&editor), vec![0.0]);
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("Rolling Hills").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../escape").is_err());
+        assert!(validate_name("a\\b").is_err());
+    }
+
+    #[test]
+    fn test_naming_prompt() -> Result<()> {
+        let mut editor = CourseEditor::new();
+        editor.course.name = String::from("custom_courses/Loop");
+        editor.start_saving();
+        editor.handle_prompt_key(KeyCode::Backspace)?;
+        editor.handle_prompt_key(KeyCode::Char('t'))?;
+        assert!(matches!(&editor.mode, CreatorMode::Naming(name) if name == "Loot"));
+        editor.handle_prompt_key(KeyCode::Esc)?;
+        assert!(!editor.is_prompting());
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut editor = CourseEditor::new();
+        editor.push([0.0, 0.0, 0.0]);
+        editor.adjust_radius(5);
+        editor.move_selected([3.0, 0.0, 0.0]);
+        assert_eq!(editor.selected_checkpoint().unwrap().radius, DEFAULT_RADIUS + 5);
+
+        editor.undo();
+        editor.undo();
+        assert_eq!(editor.selected_checkpoint().unwrap().radius, DEFAULT_RADIUS);
+        assert_eq!(xs(&editor), vec![0.0]);
+
+        editor.redo();
+        assert_eq!(editor.selected_checkpoint().unwrap().radius, DEFAULT_RADIUS + 5);
+
+        editor.undo();
+        editor.undo();
+        assert!(editor.course.checkpoints.is_empty());
+        assert_eq!(editor.selected, None);
+    }
+}